@@ -0,0 +1,38 @@
+//! Check pattern parsing never panics, whatever the dialect and flags.
+//!
+//! Run with `cargo +nightly fuzz run parse fuzz/corpus/parse fuzz/seeds/parse` from the
+//! repository root, the seeds being huge patterns that used to overflow the stack.
+#![no_main]
+
+use grep_starter_rust::{re_parse_basic, re_parse_perl, re_parse_with_flags, Flags};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    // First byte selects flags, the rest is the pattern.
+    let Some((&selector, pattern)) = data.split_first() else {
+        return;
+    };
+    let Ok(pattern) = std::str::from_utf8(pattern) else {
+        return;
+    };
+
+    let flags = Flags {
+        case_insensitive: selector & 1 != 0,
+        multi_line: selector & 2 != 0,
+        dot_all: selector & 4 != 0,
+        verbose: selector & 8 != 0,
+        bytes: selector & 16 != 0,
+    };
+    let parsed = match selector >> 5 {
+        0 => re_parse_basic(pattern, flags),
+        1 => re_parse_perl(pattern, flags),
+        _ => re_parse_with_flags(pattern, flags),
+    };
+
+    // Parsed patterns are also matched, with a small limit so slow ones do not stall fuzzing.
+    if let Ok(re) = parsed {
+        let re = re.with_match_limit(10_000);
+        let _ = re.try_find(pattern);
+        let _ = re.try_is_match_bytes(data);
+    }
+});
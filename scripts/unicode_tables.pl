@@ -0,0 +1,116 @@
+#!/usr/bin/env perl
+#
+# Generate `src/unicode_tables.rs` from the Unicode database shipped with perl.
+#
+# Usage: perl scripts/unicode_tables.pl > src/unicode_tables.rs
+use strict;
+use warnings;
+
+use Unicode::UCD qw(prop_invlist prop_values prop_value_aliases prop_aliases);
+
+my @BINARY_PROPERTIES = qw(
+    Alphabetic Dash Emoji Emoji_Presentation Hex_Digit Ideographic
+    Lowercase Math Uppercase White_Space
+);
+
+my %tables;    # canonical name => ranges
+my %aliases;   # normalized alias => canonical name
+
+sub normalize {
+    my ($name) = @_;
+    $name = lc $name;
+    $name =~ s/[ _-]//g;
+    return $name;
+}
+
+sub add_alias {
+    my ($alias, $canonical) = @_;
+    my $key = normalize($alias);
+    if (exists $aliases{$key} && $aliases{$key} ne $canonical) {
+        die "alias '$alias' used by both '$aliases{$key}' and '$canonical'";
+    }
+    $aliases{$key} = $canonical;
+}
+
+# Convert an inversion list to inclusive ranges, leaving out surrogates that are not valid `char`.
+sub ranges {
+    my @invlist = @_;
+    push @invlist, 0x110000 if @invlist % 2;
+
+    my @ranges;
+    while (my ($start, $end) = splice(@invlist, 0, 2)) {
+        $end -= 1;
+        if ($start < 0xD800 && $end > 0xDFFF) {
+            push @ranges, [$start, 0xD7FF], [0xE000, $end];
+        } elsif ($start >= 0xD800 && $start <= 0xDFFF) {
+            push @ranges, [0xE000, $end] if $end > 0xDFFF;
+        } elsif ($end >= 0xD800 && $end <= 0xDFFF) {
+            push @ranges, [$start, 0xD7FF] if $start < 0xD800;
+        } else {
+            push @ranges, [$start, $end];
+        }
+    }
+    return \@ranges;
+}
+
+sub add_table {
+    my ($canonical, $query, @names) = @_;
+    $tables{$canonical} = ranges(prop_invlist($query));
+    add_alias($_, $canonical) for ($canonical, @names);
+}
+
+for my $category (prop_values("General_Category")) {
+    my ($short, $long, @others) = prop_value_aliases("General_Category", $category);
+    next if $short eq "Cs";
+    add_table($long, "General_Category=$short", $short, @others);
+}
+
+for my $script (prop_values("Script")) {
+    my ($short, $long) = prop_value_aliases("Script", $script);
+    # Some values such as `Katakana_Or_Hiragana` are no longer assigned to any code point.
+    next unless defined $long;
+    add_table($long, "Script=$short", $short);
+}
+
+for my $property (@BINARY_PROPERTIES) {
+    my ($short, $long, @others) = prop_aliases($property);
+    add_table($long, $long, $short, @others);
+}
+
+$tables{Any} = [[0, 0xD7FF], [0xE000, 0x10FFFF]];
+add_alias("Any", "Any");
+$tables{ASCII} = [[0, 0x7F]];
+add_alias("ASCII", "ASCII");
+
+sub const_name {
+    my ($name) = @_;
+    $name = uc $name;
+    $name =~ s/[^A-Z0-9]/_/g;
+    return $name;
+}
+
+print "// DO NOT EDIT: generated by `scripts/unicode_tables.pl` from Unicode ", Unicode::UCD::UnicodeVersion(), ".\n\n";
+print "pub const UNICODE_VERSION: &str = \"", Unicode::UCD::UnicodeVersion(), "\";\n\n";
+
+print "/// Normalized property name or alias to canonical name, sorted by alias.\n";
+print "pub static PROPERTY_ALIASES: &[(&str, &str)] = &[\n";
+for my $alias (sort keys %aliases) {
+    print "    (\"$alias\", \"$aliases{$alias}\"),\n";
+}
+print "];\n\n";
+
+print "/// Canonical property name to its code point ranges, sorted by name.\n";
+print "pub static PROPERTIES: &[(&str, &[(char, char)])] = &[\n";
+for my $name (sort keys %tables) {
+    print "    (\"$name\", ", const_name($name), "),\n";
+}
+print "];\n";
+
+for my $name (sort keys %tables) {
+    print "\nstatic ", const_name($name), ": &[(char, char)] = &[\n";
+    my @ranges = @{$tables{$name}};
+    while (my @line = splice(@ranges, 0, 4)) {
+        print "   ", (map { sprintf(" ('\\u{%x}', '\\u{%x}'),", @$_) } @line), "\n";
+    }
+    print "];\n";
+}
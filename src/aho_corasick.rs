@@ -0,0 +1,202 @@
+use std::collections::VecDeque;
+
+use crate::{fixed::is_word_boundary, MatchResult};
+
+const ROOT: u32 = 0;
+
+/// Automaton searching many plain strings at once, in a single pass over the haystack.
+///
+/// Matches are reported leftmost-longest, like grep does. Case is only ignored for ASCII
+/// chars.
+#[derive(Debug, Clone)]
+pub struct AhoCorasick {
+    states: Vec<State>,
+    /// Transitions of root state, which is visited for most haystack bytes.
+    root: Box<[u32; 256]>,
+    pattern_lens: Vec<usize>,
+    max_len: usize,
+    case_insensitive: bool,
+    whole_word: bool,
+}
+
+#[derive(Debug, Clone, Default)]
+struct State {
+    /// Transitions sorted by byte.
+    next: Vec<(u8, u32)>,
+    /// State reached for the longest proper suffix of this state that is a pattern prefix.
+    fail: u32,
+    /// Patterns ending at this state, including ones that are suffixes.
+    outputs: Vec<usize>,
+}
+
+impl State {
+    fn next(&self, byte: u8) -> Option<u32> {
+        self.next
+            .binary_search_by_key(&byte, |(byte, _)| *byte)
+            .ok()
+            .map(|idx| self.next[idx].1)
+    }
+}
+
+impl AhoCorasick {
+    pub fn new<I, P>(patterns: I, case_insensitive: bool) -> Self
+    where
+        I: IntoIterator<Item = P>,
+        P: AsRef<[u8]>,
+    {
+        let fold = |byte: u8| {
+            if case_insensitive {
+                byte.to_ascii_lowercase()
+            } else {
+                byte
+            }
+        };
+
+        let mut states = vec![State::default()];
+        let mut pattern_lens = Vec::new();
+
+        // Build trie of patterns.
+        for (pattern_idx, pattern) in patterns.into_iter().enumerate() {
+            let pattern = pattern.as_ref();
+            let mut current = ROOT;
+
+            for byte in pattern.iter().copied().map(fold) {
+                current = match states[current as usize].next(byte) {
+                    Some(next) => next,
+                    None => {
+                        let next = states.len() as u32;
+                        states.push(State::default());
+                        let transitions = &mut states[current as usize].next;
+                        let idx = transitions.partition_point(|(other, _)| *other < byte);
+                        transitions.insert(idx, (byte, next));
+                        next
+                    }
+                };
+            }
+
+            states[current as usize].outputs.push(pattern_idx);
+            pattern_lens.push(pattern.len());
+        }
+
+        // Compute failure links breadth first, so they are known for shorter prefixes.
+        let mut queue: VecDeque<u32> = states[ROOT as usize]
+            .next
+            .iter()
+            .map(|(_, next)| *next)
+            .collect();
+
+        while let Some(current) = queue.pop_front() {
+            for (byte, next) in states[current as usize].next.clone() {
+                let mut fail = states[current as usize].fail;
+                let fail = loop {
+                    match states[fail as usize].next(byte) {
+                        Some(target) => break target,
+                        None if fail == ROOT => break ROOT,
+                        None => fail = states[fail as usize].fail,
+                    }
+                };
+
+                let inherited = states[fail as usize].outputs.clone();
+                let state = &mut states[next as usize];
+                state.fail = fail;
+                state.outputs.extend(inherited);
+                queue.push_back(next);
+            }
+        }
+
+        let mut root = Box::new([ROOT; 256]);
+        for (byte, next) in &states[ROOT as usize].next {
+            root[*byte as usize] = *next;
+        }
+
+        Self {
+            states,
+            root,
+            max_len: pattern_lens.iter().copied().max().unwrap_or(0),
+            pattern_lens,
+            case_insensitive,
+            whole_word: false,
+        }
+    }
+
+    /// Only match patterns when they are not surrounded by word chars.
+    pub fn whole_word(mut self, whole_word: bool) -> Self {
+        self.whole_word = whole_word;
+        self
+    }
+
+    pub fn is_match(&self, haystack: &[u8]) -> bool {
+        self.find(haystack).is_some()
+    }
+
+    /// Find leftmost-longest match, returning its pattern index with its span.
+    pub fn find(&self, haystack: &[u8]) -> Option<(usize, MatchResult)> {
+        self.find_at(haystack, 0)
+    }
+
+    /// Find leftmost-longest match starting at or after `start`.
+    pub fn find_at(&self, haystack: &[u8], start: usize) -> Option<(usize, MatchResult)> {
+        let mut best: Option<(usize, MatchResult)> = None;
+
+        // Empty patterns match everywhere, but a longer match may start at same position.
+        for (pattern, len) in self.pattern_lens.iter().enumerate() {
+            if *len == 0 && self.is_valid(haystack, start, start) {
+                best = Some((pattern, (start, start)));
+                break;
+            }
+        }
+
+        let mut current = ROOT;
+        for (index, byte) in haystack.iter().enumerate().skip(start) {
+            // Later matches cannot start before the best one.
+            if let Some((_, (best_start, _))) = best {
+                if index + 1 > best_start + self.max_len {
+                    break;
+                }
+            }
+
+            current = self.next_state(current, *byte);
+
+            for pattern in &self.states[current as usize].outputs {
+                let end = index + 1;
+                let match_start = end - self.pattern_lens[*pattern];
+                if !self.is_valid(haystack, match_start, end) {
+                    continue;
+                }
+
+                let is_better = best.is_none_or(|(best_pattern, (best_start, best_end))| {
+                    (match_start, best_end, *pattern) < (best_start, end, best_pattern)
+                });
+                if is_better {
+                    best = Some((*pattern, (match_start, end)));
+                }
+            }
+        }
+
+        best
+    }
+
+    fn next_state(&self, mut current: u32, byte: u8) -> u32 {
+        let byte = if self.case_insensitive {
+            byte.to_ascii_lowercase()
+        } else {
+            byte
+        };
+
+        loop {
+            if current == ROOT {
+                return self.root[byte as usize];
+            }
+
+            let state = &self.states[current as usize];
+            match state.next(byte) {
+                Some(next) => return next,
+                None => current = state.fail,
+            }
+        }
+    }
+
+    fn is_valid(&self, haystack: &[u8], start: usize, end: usize) -> bool {
+        !self.whole_word || is_word_boundary(haystack, start, end)
+    }
+}
@@ -0,0 +1,100 @@
+use std::ops::Range;
+
+use crate::{Flags, UnicodeProperty};
+
+/// Node of a pattern syntax tree, see [`parse_ast`](crate::parse_ast).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Ast {
+    pub kind: AstKind,
+    /// Byte range of the node in the pattern.
+    pub span: Range<usize>,
+    /// Flags in effect where the node appears.
+    pub flags: Flags,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AstKind {
+    Literal(char),
+    /// Non-ASCII byte written as `\xNN`, in byte mode only.
+    Byte(u8),
+    /// `.`, matching line terminators or not depending on flags.
+    Dot,
+    Class(Class),
+    Assertion(Assertion),
+    BackReference(usize),
+    /// Inline flags like `(?i)`, changing the flags of the rest of the enclosing group.
+    Flags,
+    Repetition {
+        ast: Box<Ast>,
+        min: usize,
+        max: Option<usize>,
+        lazy: bool,
+    },
+    Group {
+        kind: GroupKind,
+        ast: Box<Ast>,
+    },
+    /// Sequence of nodes, empty for an empty alternative.
+    Concat(Vec<Ast>),
+    /// Two or more alternatives, each one being a [`AstKind::Concat`].
+    Alternation(Vec<Ast>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Class {
+    /// `\d`
+    Digit,
+    /// `\w`
+    Word,
+    /// `\p{...}` or `\P{...}`
+    Property {
+        property: UnicodeProperty,
+        negated: bool,
+    },
+    /// `[...]` bracket expression.
+    Bracket {
+        items: Vec<BracketItem>,
+        negated: bool,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BracketItem {
+    Char(char),
+    /// Non-ASCII byte written as `\xNN`, in byte mode only.
+    Byte(u8),
+    Property {
+        property: UnicodeProperty,
+        negated: bool,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Assertion {
+    /// `^` or `\A`
+    Start,
+    /// `$` or `\z`
+    End,
+    /// `\Z`
+    EndBeforeNewline,
+    /// `^` in multi-line mode.
+    LineStart,
+    /// `$` in multi-line mode.
+    LineEnd,
+    /// `\K`
+    ResetStart,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GroupKind {
+    /// Capturing group, with its number.
+    Capture(usize),
+    /// `(?:...)`, or `(?i:...)` when setting flags.
+    NonCapture,
+    LookAhead {
+        negative: bool,
+    },
+    LookBehind {
+        negative: bool,
+    },
+}
@@ -0,0 +1,91 @@
+use crate::{OffsetMap, ParseError, ParseErrorKind};
+
+/// Translate a POSIX basic regular expression (BRE) to the extended syntax parsed by
+/// [`re_parse`](crate::re_parse), supporting GNU `\+`, `\?` and `\|` extensions, and the
+/// `\<`, `\>`, `` \` `` and `\'` anchors.
+///
+/// Groups, repetitions and alternations are written `\(..\)`, `\{n,m\}` and `\|`, while
+/// `(`, `{`, `|`, `+` and `?` are plain chars. `*` is literal when there is nothing to repeat,
+/// and `^` / `$` are only anchors at start / end of pattern, group or alternative.
+pub fn basic_to_extended(pattern: &str) -> Result<String, ParseError> {
+    basic_to_extended_with_offsets(pattern).map(|(output, _)| output)
+}
+
+/// Translate basic regular expression like [`basic_to_extended`], with the offsets of the
+/// translation in the pattern.
+pub fn basic_to_extended_with_offsets(pattern: &str) -> Result<(String, OffsetMap), ParseError> {
+    let mut output = String::with_capacity(pattern.len());
+    let mut depth = 0_usize;
+    // Set at start of pattern, group and alternative, where `*` and `^` are special.
+    let mut at_start = true;
+    let mut offsets = OffsetMap::default();
+
+    let error = |kind, span| ParseError::new(pattern, kind, span);
+    let mut chars = pattern.char_indices().peekable();
+    while let Some((idx, c)) = chars.next() {
+        offsets.push(output.len(), idx);
+        let was_at_start = std::mem::replace(&mut at_start, false);
+
+        match c {
+            '\\' => {
+                let (_, escaped) = chars
+                    .next()
+                    .ok_or_else(|| error(ParseErrorKind::TrailingBackslash, idx..idx + 1))?;
+                // GNU word and buffer anchors, which the extended syntax reads as literal chars.
+                let anchor = match escaped {
+                    '<' => Some(r"(?<!\w)(?=\w)"),
+                    '>' => Some(r"(?<=\w)(?!\w)"),
+                    '`' => Some(r"\A"),
+                    '\'' => Some(r"\z"),
+                    _ => None,
+                };
+                if let Some(anchor) = anchor {
+                    output.push_str(anchor);
+                    continue;
+                }
+
+                match escaped {
+                    '(' => depth += 1,
+                    ')' => {
+                        depth = depth.checked_sub(1).ok_or_else(|| {
+                            error(ParseErrorKind::UnmatchedParenthesis, idx..idx + 2)
+                        })?
+                    }
+                    '{' | '}' | '|' | '+' | '?' => {}
+                    _ => output.push('\\'),
+                }
+                output.push(escaped);
+                at_start = matches!(escaped, '(' | '|');
+            }
+            '(' | ')' | '{' | '}' | '|' | '+' | '?' => {
+                output.push('\\');
+                output.push(c);
+            }
+            '^' if was_at_start => {
+                output.push(c);
+                at_start = true;
+            }
+            '^' => output.push_str(r"\^"),
+            '*' if was_at_start => output.push_str(r"\*"),
+            '$' if !is_end(&pattern[idx + 1..]) => output.push_str(r"\$"),
+            '[' => {
+                // Bracket expressions are the same in both syntaxes.
+                let end = pattern[idx..]
+                    .find(']')
+                    .ok_or_else(|| error(ParseErrorKind::UnclosedBracket, idx..idx + 1))?;
+                let end = idx + end;
+                output.push_str(&pattern[idx..=end]);
+                while chars.next_if(|(idx, _)| *idx <= end).is_some() {}
+            }
+            _ => output.push(c),
+        }
+    }
+
+    offsets.finish(output.len(), pattern.len());
+    Ok((output, offsets))
+}
+
+/// Check if `$` is followed by the end of pattern, group or alternative.
+fn is_end(input: &str) -> bool {
+    input.is_empty() || input.starts_with(r"\)") || input.starts_with(r"\|")
+}
@@ -0,0 +1,55 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Instant,
+};
+
+use crate::GrepError;
+
+/// Number of steps or input bytes between two checks for an interrupted search.
+pub(crate) const CHECK_INTERVAL: usize = 4096;
+
+/// Handle to abort searches from another thread, shared by its clones.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Make running and future searches using this token fail with [`GrepError::Cancelled`].
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
+
+/// Conditions stopping a search before it completes.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Interrupt {
+    pub(crate) deadline: Option<Instant>,
+    pub(crate) token: Option<CancelToken>,
+}
+
+impl Interrupt {
+    pub(crate) fn check(&self) -> Result<(), GrepError> {
+        if self.token.as_ref().is_some_and(CancelToken::is_cancelled) {
+            return Err(GrepError::Cancelled);
+        }
+        if self
+            .deadline
+            .is_some_and(|deadline| Instant::now() >= deadline)
+        {
+            return Err(GrepError::DeadlineExceeded);
+        }
+        Ok(())
+    }
+}
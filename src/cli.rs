@@ -0,0 +1,160 @@
+use std::{fs, time::Duration};
+
+use grep_starter_rust::Flags;
+
+/// What to print for searched files.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub enum OutputMode {
+    /// Matching lines.
+    #[default]
+    Lines,
+    /// Number of matching lines, with `-c`.
+    Count,
+    /// Names of files with a matching line, with `-l`.
+    FilesWithMatches,
+    /// Nothing, only the exit status tells if something matched, with `-q`.
+    Quiet,
+}
+
+/// When to highlight matches, with `--color`.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub enum ColorChoice {
+    #[default]
+    Never,
+    Always,
+    /// Only when writing to a terminal.
+    Auto,
+}
+
+/// Syntax of regex patterns.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub enum Syntax {
+    /// POSIX basic regexes, with `-G` or by default.
+    #[default]
+    Basic,
+    /// POSIX extended regexes, with `-E`.
+    Extended,
+    /// Perl-compatible regexes, with `-P`.
+    Perl,
+}
+
+/// Command line options.
+#[derive(Debug, Default)]
+pub struct Args {
+    pub patterns: Vec<String>,
+    pub files: Vec<String>,
+    pub flags: Flags,
+    pub mode: OutputMode,
+    /// Patterns are plain strings instead of regexes, with `-F`.
+    pub fixed_strings: bool,
+    pub syntax: Syntax,
+    /// Matches must not be surrounded by word chars, with `-w`.
+    pub whole_word: bool,
+    /// Print matched parts of lines instead of whole lines, with `-o`.
+    pub only_matching: bool,
+    pub color: ColorChoice,
+    /// Maximum number of backtracking steps per line, with `--match-limit`.
+    pub match_limit: Option<usize>,
+    /// Maximum duration of the whole search, with `--timeout`.
+    pub timeout: Option<Duration>,
+}
+
+impl Args {
+    pub fn parse(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut output = Self::default();
+        let mut pattern_files = Vec::new();
+        let mut positionals = Vec::new();
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-E" | "--extended-regexp" => output.syntax = Syntax::Extended,
+                "-G" | "--basic-regexp" => output.syntax = Syntax::Basic,
+                "-P" | "--perl-regexp" => output.syntax = Syntax::Perl,
+                "-F" | "--fixed-strings" => output.fixed_strings = true,
+                "-i" | "--ignore-case" => output.flags.case_insensitive = true,
+                "-w" | "--word-regexp" => output.whole_word = true,
+                "-o" | "--only-matching" => output.only_matching = true,
+                "--color" | "--colour" => output.color = ColorChoice::Auto,
+                _ if arg.starts_with("--color=") || arg.starts_with("--colour=") => {
+                    output.color = match arg.split_once('=').map(|(_, when)| when) {
+                        Some("always" | "yes" | "force") => ColorChoice::Always,
+                        Some("never" | "no" | "none") => ColorChoice::Never,
+                        Some("auto" | "tty" | "if-tty") => ColorChoice::Auto,
+                        _ => return Err(format!("Invalid argument '{arg}'")),
+                    }
+                }
+                "--match-limit" => {
+                    let value = next_value(&mut args, &arg)?;
+                    output.match_limit = Some(parse_limit(&value)?);
+                }
+                _ if arg.starts_with("--match-limit=") => {
+                    output.match_limit = Some(parse_limit(&arg["--match-limit=".len()..])?);
+                }
+                "--timeout" => {
+                    let value = next_value(&mut args, &arg)?;
+                    output.timeout = Some(parse_timeout(&value)?);
+                }
+                _ if arg.starts_with("--timeout=") => {
+                    output.timeout = Some(parse_timeout(&arg["--timeout=".len()..])?);
+                }
+                "-e" => output.patterns.push(next_value(&mut args, &arg)?),
+                "-f" => pattern_files.push(next_value(&mut args, &arg)?),
+                "--verbose-regex" => output.flags.verbose = true,
+                "--dotall" => output.flags.dot_all = true,
+                "-c" | "--count" => output.mode = OutputMode::Count,
+                "-l" | "--files-with-matches" => output.mode = OutputMode::FilesWithMatches,
+                "-q" | "--quiet" | "--silent" => output.mode = OutputMode::Quiet,
+                _ if arg.starts_with('-') && arg != "-" => {
+                    return Err(format!("Unknown argument '{arg}'"))
+                }
+                _ => positionals.push(arg),
+            }
+        }
+
+        for path in pattern_files {
+            let content =
+                fs::read_to_string(&path).map_err(|e| format!("Cannot read '{path}': {e}"))?;
+
+            // Verbose patterns may span multiple lines, so the whole file is a single pattern.
+            if output.flags.verbose {
+                output.patterns.push(content);
+            } else {
+                output
+                    .patterns
+                    .extend(content.lines().map(|line| line.to_string()));
+            }
+        }
+
+        // First positional argument is the pattern if none was given with `-e` / `-f`.
+        let mut positionals = positionals.into_iter();
+        if output.patterns.is_empty() {
+            let pattern = positionals.next().ok_or("Expected a pattern")?;
+            output.patterns.push(pattern);
+        }
+
+        // Remaining ones are files to search, standard input being used if there is none.
+        output.files.extend(positionals);
+
+        Ok(output)
+    }
+}
+
+fn next_value(args: &mut impl Iterator<Item = String>, name: &str) -> Result<String, String> {
+    args.next()
+        .ok_or_else(|| format!("Expected a value after '{name}'"))
+}
+
+fn parse_limit(value: &str) -> Result<usize, String> {
+    value
+        .parse()
+        .map_err(|_| format!("Invalid match limit '{value}'"))
+}
+
+/// Parse timeout in seconds, which can be fractional.
+fn parse_timeout(value: &str) -> Result<Duration, String> {
+    value
+        .parse()
+        .ok()
+        .and_then(|secs| Duration::try_from_secs_f64(secs).ok())
+        .ok_or_else(|| format!("Invalid timeout '{value}'"))
+}
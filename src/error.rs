@@ -1,7 +1,205 @@
+use std::{fmt, iter, ops::Range};
+
 use thiserror::Error;
 
 #[derive(Debug, PartialEq, Eq, Error)]
 pub enum GrepError {
-    #[error("invalid pattern")]
-    InvalidPattern,
+    #[error(transparent)]
+    Parse(#[from] ParseError),
+    #[error("match limit exceeded")]
+    MatchLimitExceeded,
+    #[error("deadline exceeded")]
+    DeadlineExceeded,
+    #[error("search cancelled")]
+    Cancelled,
+}
+
+/// Reason a pattern could not be parsed.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum ParseErrorKind {
+    #[error("empty pattern")]
+    EmptyPattern,
+    #[error("empty group or alternative")]
+    EmptyGroup,
+    #[error("unclosed group")]
+    UnclosedGroup,
+    #[error("unmatched closing parenthesis")]
+    UnmatchedParenthesis,
+    #[error("unclosed bracket expression")]
+    UnclosedBracket,
+    #[error("nothing to repeat")]
+    NothingToRepeat,
+    #[error("invalid repetition count")]
+    InvalidRepetition,
+    #[error("groups and repetitions nested too deep")]
+    NestingTooDeep,
+    #[error("repetition too large")]
+    RepetitionTooLarge,
+    #[error("invalid range")]
+    InvalidRange,
+    #[error("trailing backslash")]
+    TrailingBackslash,
+    #[error("invalid escape sequence")]
+    InvalidEscape,
+    #[error("unknown inline flag")]
+    UnknownFlag,
+    #[error("unknown unicode property '{0}'")]
+    UnknownProperty(String),
+    #[error("unicode class in byte mode")]
+    UnicodeInByteMode,
+    #[error("lookbehind must have a bounded length")]
+    UnboundedLookBehind,
+    #[error("invalid group name")]
+    InvalidGroupName,
+    #[error("duplicate group name '{0}'")]
+    DuplicateGroupName(String),
+    #[error("unknown group name '{0}'")]
+    UnknownGroupName(String),
+    #[error("invalid group reference")]
+    InvalidGroupReference,
+    #[error("unsupported syntax '{0}'")]
+    UnsupportedSyntax(String),
+}
+
+/// Pattern parse error, with the byte span of the offending part of the pattern.
+///
+/// Displayed as the reason followed by the pattern line, with carets under the span.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub kind: ParseErrorKind,
+    pub span: Range<usize>,
+    pub pattern: String,
+}
+
+impl ParseError {
+    pub fn new(pattern: &str, kind: ParseErrorKind, span: Range<usize>) -> Self {
+        Self {
+            kind,
+            span,
+            pattern: pattern.to_string(),
+        }
+    }
+
+    /// Point error into the pattern translated to the one it was raised for, like basic
+    /// syntax being translated to the extended one.
+    pub fn remap(self, original: &str, offsets: &OffsetMap) -> Self {
+        let span = offsets.map(self.span);
+        let mut start = span.start.min(original.len());
+        while !original.is_char_boundary(start) {
+            start -= 1;
+        }
+        let mut end = span.end.clamp(start, original.len());
+        while !original.is_char_boundary(end) {
+            end += 1;
+        }
+        Self::new(original, self.kind, start..end)
+    }
+}
+
+/// Offsets in a pattern of its translation, so errors can point into the pattern as written.
+///
+/// Pieces of the translation are recorded with the original offset they come from. Offsets in
+/// a piece as long as the original text are mapped one to one, other pieces standing for their
+/// whole original text.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OffsetMap {
+    /// Translated and original start offsets of pieces, in increasing order.
+    pieces: Vec<(usize, usize)>,
+    /// Translated and original lengths.
+    lens: (usize, usize),
+}
+
+impl OffsetMap {
+    /// Map of a pattern to itself.
+    pub fn identity(len: usize) -> Self {
+        Self {
+            pieces: vec![(0, 0)],
+            lens: (len, len),
+        }
+    }
+
+    /// Record translated piece starting at `translated`, coming from `original` offset.
+    pub(crate) fn push(&mut self, translated: usize, original: usize) {
+        self.pieces.push((translated, original));
+    }
+
+    /// Set lengths of the translated and original patterns, once translated.
+    pub(crate) fn finish(&mut self, translated: usize, original: usize) {
+        self.lens = (translated, original);
+    }
+
+    /// Map translation wrapped in a `prefix` and `suffix` of these lengths, standing for the
+    /// start and end of the original pattern.
+    pub fn wrap(&mut self, prefix: usize, suffix: usize) {
+        let (translated, original) = self.lens;
+        self.pieces = iter::once((0, 0))
+            .chain(
+                self.pieces
+                    .iter()
+                    .map(|&(start, from)| (prefix + start, from)),
+            )
+            .chain(iter::once((prefix + translated, original)))
+            .collect();
+        self.lens = (prefix + translated + suffix, original);
+    }
+
+    /// Map span of the translation to the original pattern.
+    pub fn map(&self, span: Range<usize>) -> Range<usize> {
+        let start = self.map_offset(span.start, false);
+        if span.is_empty() {
+            return start..start;
+        }
+        start..self.map_offset(span.end, true).max(start)
+    }
+
+    /// Map offset, as the end of the piece before it when `is_end`.
+    fn map_offset(&self, offset: usize, is_end: bool) -> usize {
+        let (translated_len, original_len) = self.lens;
+        if offset >= translated_len {
+            return original_len;
+        }
+        let in_piece = if is_end { offset - 1 } else { offset };
+        let idx = self
+            .pieces
+            .partition_point(|&(start, _)| start <= in_piece)
+            .saturating_sub(1);
+        let Some(&(start, from)) = self.pieces.get(idx) else {
+            return offset.min(original_len);
+        };
+        let (end, to) = self.pieces.get(idx + 1).copied().unwrap_or(self.lens);
+
+        if end - start == to - from {
+            from + offset - start
+        } else if is_end {
+            to
+        } else {
+            from
+        }
+    }
 }
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Only the line holding the span start is shown, for multi-line verbose patterns.
+        let start = self.span.start.min(self.pattern.len());
+        let line_start = self.pattern[..start].rfind('\n').map_or(0, |idx| idx + 1);
+        let line_end = self.pattern[start..]
+            .find('\n')
+            .map_or(self.pattern.len(), |idx| start + idx);
+        let end = self.span.end.clamp(start, line_end);
+
+        let column = self.pattern[line_start..start].chars().count();
+        let width = self.pattern[start..end].chars().count().max(1);
+        write!(
+            f,
+            "{} at offset {}\n  {}\n  {}{}",
+            self.kind,
+            self.span.start,
+            &self.pattern[line_start..line_end],
+            " ".repeat(column),
+            "^".repeat(width)
+        )
+    }
+}
+
+impl std::error::Error for ParseError {}
@@ -0,0 +1,116 @@
+use crate::{decode_char, is_word_char, Flags, MatchResult, Regexp};
+
+/// Plain string pattern, searched with the Boyer-Moore-Horspool algorithm.
+///
+/// Used by `-F`, where patterns do not have any special char.
+#[derive(Debug, Clone)]
+pub struct FixedString {
+    needle: Vec<u8>,
+    case_insensitive: bool,
+    whole_word: bool,
+    /// How far the needle can move when its last byte is aligned with a given haystack byte.
+    shifts: Box<[usize; 256]>,
+    /// Non ASCII needles ignoring case need unicode case folding, done by a regex.
+    folded: Option<Regexp>,
+}
+
+impl FixedString {
+    pub fn new(needle: &str, case_insensitive: bool) -> Self {
+        let bytes = needle.as_bytes();
+        let fold = |byte: u8| {
+            if case_insensitive {
+                byte.to_ascii_lowercase()
+            } else {
+                byte
+            }
+        };
+
+        let mut shifts = Box::new([bytes.len().max(1); 256]);
+        for (idx, byte) in bytes.iter().enumerate().take(bytes.len().saturating_sub(1)) {
+            let shift = bytes.len() - 1 - idx;
+            shifts[fold(*byte) as usize] = shift;
+            shifts[fold(*byte).to_ascii_uppercase() as usize] = shift;
+        }
+
+        let folded = (case_insensitive && !needle.is_ascii()).then(|| {
+            let flags = Flags {
+                case_insensitive,
+                ..Flags::default()
+            };
+            Regexp::from(needle.chars().map(|c| flags.literal(c)).collect::<Vec<_>>())
+        });
+
+        Self {
+            needle: bytes.to_vec(),
+            case_insensitive,
+            whole_word: false,
+            shifts,
+            folded,
+        }
+    }
+
+    /// Only match the needle when it is not surrounded by word chars.
+    pub fn whole_word(mut self, whole_word: bool) -> Self {
+        self.whole_word = whole_word;
+        self
+    }
+
+    pub fn is_match(&self, haystack: &[u8]) -> bool {
+        self.find(haystack).is_some()
+    }
+
+    /// Find first occurrence of needle in haystack, which does not need to be valid UTF-8.
+    pub fn find(&self, haystack: &[u8]) -> Option<MatchResult> {
+        self.find_at(haystack, 0)
+    }
+
+    /// Find first occurrence of needle starting at or after `start`.
+    pub fn find_at(&self, haystack: &[u8], mut start: usize) -> Option<MatchResult> {
+        while let Some((match_start, match_end)) = self.find_occurrence(haystack, start) {
+            if !self.whole_word || is_word_boundary(haystack, match_start, match_end) {
+                return Some((match_start, match_end));
+            }
+            start = match_start + 1;
+        }
+
+        None
+    }
+
+    fn find_occurrence(&self, haystack: &[u8], start: usize) -> Option<MatchResult> {
+        if let Some(folded) = &self.folded {
+            let (match_start, match_end) = folded.find_bytes(haystack.get(start..)?)?;
+            return Some((start + match_start, start + match_end));
+        }
+
+        let len = self.needle.len();
+        let mut idx = start;
+
+        while idx + len <= haystack.len() {
+            let window = &haystack[idx..idx + len];
+            let found = if self.case_insensitive {
+                window.eq_ignore_ascii_case(&self.needle)
+            } else {
+                window == self.needle
+            };
+            if found {
+                return Some((idx, idx + len));
+            }
+
+            // Empty needle always matches, so there is a last byte here.
+            idx += self.shifts[window[len - 1] as usize];
+        }
+
+        None
+    }
+}
+
+/// Check that match is neither preceded nor followed by a word char.
+pub(crate) fn is_word_boundary(haystack: &[u8], start: usize, end: usize) -> bool {
+    let is_word = |c: Option<char>| c.is_some_and(is_word_char);
+
+    let before = (1..=start.min(4))
+        .find_map(|len| decode_char(&haystack[start - len..start]).filter(|c| c.len_utf8() == len));
+    let after = decode_char(&haystack[end..]);
+
+    !is_word(before) && !is_word(after)
+}
@@ -0,0 +1,273 @@
+use std::{collections::HashMap, str};
+
+use crate::{
+    cancel::{Interrupt, CHECK_INTERVAL},
+    program::{Inst, Program},
+    GrepError, Pattern,
+};
+
+/// Maximum number of cached states, the cache being cleared when full.
+const MAX_STATES: usize = 4096;
+/// Number of cache clears tolerated before checking if the cache is thrashing.
+const MAX_CLEARS: usize = 8;
+/// Minimum bytes scanned per built state for the cache to be worth it.
+const MIN_BYTES_PER_STATE: usize = 8;
+
+const UNKNOWN: u32 = u32::MAX;
+const MATCH: u32 = u32::MAX - 1;
+
+/// DFA built lazily from a compiled program, only answering whether input matches.
+///
+/// States are sets of NFA threads, created on demand while scanning input and cached with
+/// their transitions so most bytes are handled by a single table lookup.
+#[derive(Debug, Clone)]
+pub(crate) struct LazyDfa {
+    program: Program,
+    states: Vec<State>,
+    /// Transitions of every state, 256 entries per state.
+    transitions: Vec<u32>,
+    ids: HashMap<State, u32>,
+    clears: usize,
+    /// Bytes scanned since last cache clear.
+    scanned: usize,
+}
+
+/// What was before current position, needed by start of text and line assertions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Context {
+    TextStart,
+    LineStart,
+    Other,
+}
+
+/// Leading bytes of a multi bytes char consumed by a unit instruction.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+struct Partial {
+    bytes: [u8; 3],
+    len: u8,
+}
+
+/// NFA threads waiting on a unit, match or not yet resolved assertion instruction.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct State {
+    threads: Vec<(usize, Partial)>,
+    context: Context,
+}
+
+impl LazyDfa {
+    /// Build DFA for program, `None` if it uses assertions looking further than next byte.
+    pub(crate) fn new(program: &Program) -> Option<Self> {
+        let supported = program.insts.iter().all(|inst| match inst {
+            Inst::Assert(pattern) => *pattern != Pattern::EndBeforeNewline,
+            _ => true,
+        });
+
+        supported.then(|| Self {
+            program: program.clone(),
+            states: Vec::new(),
+            transitions: Vec::new(),
+            ids: HashMap::new(),
+            clears: 0,
+            scanned: 0,
+        })
+    }
+
+    /// Check if program matches anywhere in input, `None` if the cache is thrashing.
+    pub(crate) fn is_match(
+        &mut self,
+        input: &[u8],
+        interrupt: &Interrupt,
+    ) -> Result<Option<bool>, GrepError> {
+        let mut threads = Vec::new();
+        self.closure(0, None, &mut threads);
+        let Some(mut current) = self.intern(State {
+            threads,
+            context: Context::TextStart,
+        }) else {
+            return Ok(None);
+        };
+
+        for (index, &byte) in input.iter().enumerate() {
+            if index.is_multiple_of(CHECK_INTERVAL) {
+                interrupt.check()?;
+            }
+
+            self.scanned += 1;
+            let next = match self.transitions[current as usize * 256 + byte as usize] {
+                UNKNOWN => match self.compute_transition(current, byte) {
+                    Some(next) => next,
+                    None => return Ok(None),
+                },
+                next => next,
+            };
+
+            match next {
+                MATCH => return Ok(Some(true)),
+                next => current = next,
+            }
+
+            // Nothing left to try, which can only happen on anchored programs.
+            if self.states[current as usize].threads.is_empty() {
+                return Ok(Some(false));
+            }
+        }
+
+        let state = &self.states[current as usize];
+        Ok(Some(self.resolve(state, None).0))
+    }
+
+    fn compute_transition(&mut self, current: u32, byte: u8) -> Option<u32> {
+        let state = &self.states[current as usize];
+        let (matched, waiting) = self.resolve(state, Some(byte));
+        if matched {
+            self.transitions[current as usize * 256 + byte as usize] = MATCH;
+            return Some(MATCH);
+        }
+
+        let mut threads = Vec::new();
+        for (pc, partial) in waiting {
+            let Inst::Unit(pattern) = &self.program.insts[pc] else {
+                continue;
+            };
+
+            let mut bytes = partial.bytes[..partial.len as usize].to_vec();
+            bytes.push(byte);
+
+            match pattern.match_unit(&bytes) {
+                Some(len) if len == bytes.len() => self.closure(pc + 1, None, &mut threads),
+                Some(_) => {}
+                None if is_char_prefix(pattern, &bytes) => {
+                    let mut partial = Partial {
+                        len: bytes.len() as u8,
+                        ..Partial::default()
+                    };
+                    partial.bytes[..bytes.len()].copy_from_slice(&bytes);
+                    threads.push((pc, partial));
+                }
+                None => {}
+            }
+        }
+
+        // A match can start at every position of unanchored programs.
+        if !self.program.anchored {
+            self.closure(0, None, &mut threads);
+        }
+
+        let context = if byte == b'\n' {
+            Context::LineStart
+        } else {
+            Context::Other
+        };
+        let clears = self.clears;
+        let next = self.intern(State { threads, context })?;
+
+        // Cache may have been cleared while interning, in which case current state is gone.
+        if self.clears == clears {
+            self.transitions[current as usize * 256 + byte as usize] = next;
+        }
+        Some(next)
+    }
+
+    /// Resolve pending assertions knowing next byte, returning if a match was reached and
+    /// threads waiting to consume next byte.
+    fn resolve(&self, state: &State, next: Option<u8>) -> (bool, Vec<(usize, Partial)>) {
+        let mut waiting = Vec::with_capacity(state.threads.len());
+
+        for &(pc, partial) in &state.threads {
+            match &self.program.insts[pc] {
+                Inst::Assert(_) => {
+                    self.closure(pc, Some((state.context, next)), &mut waiting);
+                }
+                _ => waiting.push((pc, partial)),
+            }
+        }
+
+        let matched = waiting
+            .iter()
+            .any(|(pc, _)| matches!(self.program.insts[*pc], Inst::Match(_)));
+        (matched, waiting)
+    }
+
+    /// Follow empty transitions from `pc`, assertions being kept pending when there is not
+    /// enough context to resolve them.
+    fn closure(
+        &self,
+        pc: usize,
+        context: Option<(Context, Option<u8>)>,
+        output: &mut Vec<(usize, Partial)>,
+    ) {
+        let mut seen = vec![false; self.program.insts.len()];
+        let mut stack = vec![pc];
+
+        while let Some(pc) = stack.pop() {
+            if std::mem::replace(&mut seen[pc], true) {
+                continue;
+            }
+
+            match &self.program.insts[pc] {
+                Inst::Unit(_) | Inst::Match(_) => output.push((pc, Partial::default())),
+                Inst::Assert(pattern) => match context {
+                    Some((context, next)) => {
+                        if assertion_holds(pattern, context, next) {
+                            stack.push(pc + 1);
+                        }
+                    }
+                    None => output.push((pc, Partial::default())),
+                },
+                Inst::Split(first, second) => {
+                    stack.push(*second);
+                    stack.push(*first);
+                }
+                Inst::Jump(target) => stack.push(*target),
+                Inst::Save(_) => stack.push(pc + 1),
+            }
+        }
+    }
+
+    /// Get id of state, building it if needed. `None` if the cache is thrashing.
+    fn intern(&mut self, mut state: State) -> Option<u32> {
+        state.threads.sort_unstable();
+        state.threads.dedup();
+
+        if let Some(id) = self.ids.get(&state) {
+            return Some(*id);
+        }
+
+        if self.states.len() >= MAX_STATES {
+            if self.clears >= MAX_CLEARS && self.scanned < MIN_BYTES_PER_STATE * MAX_STATES {
+                return None;
+            }
+
+            self.clears += 1;
+            self.scanned = 0;
+            self.states.clear();
+            self.transitions.clear();
+            self.ids.clear();
+        }
+
+        let id = self.states.len() as u32;
+        self.ids.insert(state.clone(), id);
+        self.states.push(state);
+        self.transitions.extend([UNKNOWN; 256]);
+        Some(id)
+    }
+}
+
+fn assertion_holds(pattern: &Pattern, context: Context, next: Option<u8>) -> bool {
+    match pattern {
+        Pattern::Start => context == Context::TextStart,
+        Pattern::LineStart => context != Context::Other,
+        Pattern::End => next.is_none(),
+        Pattern::LineEnd => matches!(next, None | Some(b'\n')),
+        _ => false,
+    }
+}
+
+/// Check if bytes are the start of a char a unit pattern may match once complete.
+fn is_char_prefix(pattern: &Pattern, bytes: &[u8]) -> bool {
+    if matches!(pattern, Pattern::Byte(_) | Pattern::ByteClass { .. }) || bytes.len() > 3 {
+        return false;
+    }
+
+    matches!(str::from_utf8(bytes), Err(e) if e.valid_up_to() == 0 && e.error_len().is_none())
+}
@@ -1,27 +1,156 @@
+mod aho_corasick;
+mod ast;
+mod backtrack;
+mod basic;
+mod cancel;
 mod error;
+mod fixed;
+mod lazy_dfa;
+mod literal;
+mod parser;
+mod perl;
+mod pikevm;
+mod printer;
+mod program;
+mod set;
+mod unicode;
+#[rustfmt::skip]
+mod unicode_tables;
 
 use std::{
-    collections::HashMap,
-    sync::atomic::{AtomicUsize, Ordering},
+    cell::{Cell, RefCell},
+    collections::{HashMap, HashSet},
+    fmt, iter, slice, str,
+    sync::{Arc, Mutex},
+    time::Instant,
 };
 
+use cancel::{Interrupt, CHECK_INTERVAL};
+use lazy_dfa::LazyDfa;
+use literal::Prefilter;
+use program::Program;
+
+pub use aho_corasick::AhoCorasick;
+pub use ast::{Assertion, Ast, AstKind, BracketItem, Class, GroupKind};
+pub use basic::{basic_to_extended, basic_to_extended_with_offsets};
+pub use cancel::CancelToken;
 pub use error::*;
+pub use fixed::FixedString;
+pub use perl::{perl_to_extended, perl_to_extended_with_offsets};
+pub use set::RegexSet;
+pub use unicode::UnicodeProperty;
+pub use unicode_tables::UNICODE_VERSION;
 
 type MatchResult = (usize, usize);
-type ReferenceTable<'a> = HashMap<usize, &'a str>;
+type ReferenceTable<'a> = HashMap<usize, &'a [u8]>;
+
+/// Maximum number of states a leftmost-longest search remembers having explored.
+const MAX_EXPLORED: usize = 1 << 16;
+
+/// Default number of steps a backtracking search can take, like PCRE `match_limit`.
+pub const DEFAULT_MATCH_LIMIT: usize = 10_000_000;
+
+/// Which match is reported when many of them start at the same position.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum MatchKind {
+    /// First match in backtracking order, like Perl: `a|ab` matches `a` in `ab`.
+    #[default]
+    LeftmostFirst,
+    /// Longest match, like POSIX and grep: `a|ab` matches `ab` in `ab`.
+    LeftmostLongest,
+}
 
-pub fn match_pattern(input_line: &str, input_pattern: &str) -> Option<MatchResult> {
-    let re = re_parse(input_pattern).expect("Unhandled pattern");
-    re.matches(input_line)
+/// Find first match of pattern in input.
+///
+/// Pattern is parsed on every call, use [`Regexp::new`] to match it against many inputs.
+pub fn match_pattern(
+    input_line: &str,
+    input_pattern: &str,
+) -> Result<Option<MatchResult>, GrepError> {
+    Regexp::new(input_pattern)?.try_find(input_line)
+}
+
+pub fn match_pattern_with_flags(
+    input_line: &str,
+    input_pattern: &str,
+    flags: Flags,
+) -> Result<Option<MatchResult>, GrepError> {
+    Regexp::with_flags(input_pattern, flags)?.try_find(input_line)
 }
 
 pub fn re_parse(input_pattern: &str) -> Result<Regexp, GrepError> {
-    Regexp::parse(input_pattern, &AtomicUsize::new(1))
+    re_parse_with_flags(input_pattern, Flags::default())
+}
+
+/// Parse pattern with initial flags, as if it was prefixed by matching `(?imsx)` group.
+pub fn re_parse_with_flags(input_pattern: &str, flags: Flags) -> Result<Regexp, GrepError> {
+    let ast = parser::parse(input_pattern, flags)?;
+    Ok(Regexp::from(parser::lower(input_pattern, &ast)?))
+}
+
+/// Parse pattern to its syntax tree, each node having its span in the pattern.
+pub fn parse_ast(input_pattern: &str, flags: Flags) -> Result<Ast, ParseError> {
+    parser::parse(input_pattern, flags)
+}
+
+/// Parse POSIX basic regular expression, see [`basic_to_extended`].
+pub fn re_parse_basic(input_pattern: &str, flags: Flags) -> Result<Regexp, GrepError> {
+    let (pattern, offsets) = basic_to_extended_with_offsets(input_pattern)?;
+    re_parse_translated(input_pattern, &pattern, &offsets, flags)
+}
+
+/// Parse Perl-compatible regular expression, see [`perl_to_extended`].
+pub fn re_parse_perl(input_pattern: &str, flags: Flags) -> Result<Regexp, GrepError> {
+    let (pattern, offsets) = perl_to_extended_with_offsets(input_pattern, flags)?;
+    re_parse_translated(input_pattern, &pattern, &offsets, flags)
 }
 
-#[derive(Debug, PartialEq, Eq)]
+/// Parse pattern translated from `input_pattern`, errors pointing into the latter.
+pub fn re_parse_translated(
+    input_pattern: &str,
+    pattern: &str,
+    offsets: &OffsetMap,
+    flags: Flags,
+) -> Result<Regexp, GrepError> {
+    re_parse_with_flags(pattern, flags).map_err(|e| match e {
+        GrepError::Parse(e) => GrepError::Parse(e.remap(input_pattern, offsets)),
+        e => e,
+    })
+}
+
+/// Matching options that can be toggled inline with `(?imsx)`.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub struct Flags {
+    pub case_insensitive: bool,
+    pub multi_line: bool,
+    pub dot_all: bool,
+    pub verbose: bool,
+    /// Match bytes instead of unicode chars, disabled by default and enabled with `(?-u)`.
+    pub bytes: bool,
+}
+
+/// Compiled regex, cheap to clone and shareable between threads.
+///
+/// Searches that cannot be run by an automaton, because of back references or lookaround,
+/// are backtracked and stop after [`DEFAULT_MATCH_LIMIT`] steps unless configured otherwise.
+/// Infallible methods then report no match, `try_*` ones returning
+/// [`GrepError::MatchLimitExceeded`]. Searches can also be given a deadline or a
+/// [`CancelToken`], checked every few thousand steps.
+#[derive(Clone)]
 pub struct Regexp {
-    pub patterns: Vec<Pattern>,
+    inner: Arc<Compiled>,
+    match_kind: MatchKind,
+    match_limit: usize,
+    interrupt: Interrupt,
+}
+
+/// Everything computed from patterns once, when building a [`Regexp`].
+struct Compiled {
+    patterns: Vec<Pattern>,
+    program: Option<Program>,
+    prefilter: Option<Prefilter>,
+    /// DFA states cached between calls, threads not getting the lock use the Pike VM instead.
+    dfa: Mutex<Option<LazyDfa>>,
 }
 
 #[derive(Debug, PartialEq, Eq, Clone)]
@@ -31,92 +160,294 @@ pub enum Pattern {
     Chars,
     PositiveCharGroup(Vec<char>),
     NegativeCharGroup(Vec<char>),
+    Property {
+        property: UnicodeProperty,
+        negated: bool,
+    },
+    CharClass {
+        items: Vec<ClassItem>,
+        negated: bool,
+    },
+    Byte(u8),
+    ByteClass {
+        bytes: Vec<u8>,
+        negated: bool,
+    },
     Start,
     End,
+    EndBeforeNewline,
+    LineStart,
+    LineEnd,
     OneOrMore(Box<Pattern>),
     ZeroOrOne(Box<Pattern>),
+    /// Lazy `+?`, repeating pattern only when the remaining ones fail.
+    LazyOneOrMore(Box<Pattern>),
+    /// Lazy `??`, trying to skip pattern first.
+    LazyZeroOrOne(Box<Pattern>),
     Wildcard,
+    AnyChar,
     Alternation {
         alternations: Vec<Vec<Pattern>>,
         id: usize,
     },
+    Group {
+        alternations: Vec<Vec<Pattern>>,
+    },
     BackReference(usize),
+    /// `\K`, reported match starting at this position, ignored in lookaround.
+    ResetStart,
+    LookAhead {
+        alternations: Vec<Vec<Pattern>>,
+        negative: bool,
+    },
+    LookBehind {
+        alternations: Vec<Vec<Pattern>>,
+        negative: bool,
+    },
+}
+
+/// Item of a bracket expression that cannot be represented as a plain char group.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum ClassItem {
+    Char(char),
+    Property {
+        property: UnicodeProperty,
+        negated: bool,
+    },
+}
+
+impl ClassItem {
+    fn matches(&self, input_char: char) -> bool {
+        match self {
+            Self::Char(c) => *c == input_char,
+            Self::Property { property, negated } => property.contains(input_char) != *negated,
+        }
+    }
 }
 
 impl Regexp {
-    fn parse(mut input: &str, alternation_counter: &AtomicUsize) -> Result<Self, GrepError> {
-        let mut patterns = Vec::new();
+    pub fn new(pattern: &str) -> Result<Self, GrepError> {
+        re_parse(pattern)
+    }
 
-        // Parse anchor
-        let start_string_anchor = if let Some(next_input) = input.strip_prefix('^') {
-            input = next_input;
-            true
-        } else {
-            false
-        };
+    /// Parse pattern with initial flags, see [`re_parse_with_flags`].
+    pub fn with_flags(pattern: &str, flags: Flags) -> Result<Self, GrepError> {
+        re_parse_with_flags(pattern, flags)
+    }
 
-        let end_string_anchor = if let Some(next_input) = input.strip_suffix('$') {
-            input = next_input;
-            true
-        } else {
-            false
-        };
+    pub fn patterns(&self) -> &[Pattern] {
+        &self.inner.patterns
+    }
 
-        // Parse pattern
-        loop {
-            if let Some(next_input) = input.strip_prefix('+') {
-                input = next_input;
-                let prev = patterns.pop().ok_or(GrepError::InvalidPattern)?;
-                patterns.push(Pattern::OneOrMore(Box::new(prev)));
-            }
+    /// Set which match is reported among those starting at the leftmost position.
+    pub fn with_match_kind(mut self, match_kind: MatchKind) -> Self {
+        self.match_kind = match_kind;
+        self
+    }
 
-            if let Some(next_input) = input.strip_prefix('?') {
-                input = next_input;
-                let prev = patterns.pop().ok_or(GrepError::InvalidPattern)?;
-                patterns.push(Pattern::ZeroOrOne(Box::new(prev)));
-            }
+    /// Set maximum number of steps a backtracking search can take.
+    pub fn with_match_limit(mut self, match_limit: usize) -> Self {
+        self.match_limit = match_limit;
+        self
+    }
+
+    /// Make searches still running at `deadline` fail with [`GrepError::DeadlineExceeded`].
+    pub fn with_deadline(mut self, deadline: Instant) -> Self {
+        self.interrupt.deadline = Some(deadline);
+        self
+    }
+
+    /// Make searches fail with [`GrepError::Cancelled`] once `token` is cancelled.
+    pub fn with_cancel_token(mut self, token: CancelToken) -> Self {
+        self.interrupt.token = Some(token);
+        self
+    }
 
-            if input.is_empty() {
-                break;
+    /// Check if the regex matches anywhere in input.
+    pub fn is_match(&self, input: &str) -> bool {
+        self.is_match_bytes(input.as_bytes())
+    }
+
+    /// Find first match in input, as a byte range.
+    pub fn find(&self, input: &str) -> Option<MatchResult> {
+        self.try_find(input).unwrap_or(None)
+    }
+
+    /// Find first match in input, failing if the search is stopped before completion.
+    pub fn try_find(&self, input: &str) -> Result<Option<MatchResult>, GrepError> {
+        self.find_from(input.as_bytes(), 0, true)
+    }
+
+    /// Check if the regex matches anywhere in input, which does not need to be valid UTF-8.
+    pub fn is_match_bytes(&self, input: &[u8]) -> bool {
+        self.try_is_match_bytes(input).unwrap_or(false)
+    }
+
+    /// Check if the regex matches anywhere in input, failing if the search is stopped early.
+    pub fn try_is_match_bytes(&self, input: &[u8]) -> Result<bool, GrepError> {
+        if !self.is_candidate(input) {
+            return Ok(false);
+        }
+
+        if let Ok(mut dfa) = self.inner.dfa.try_lock() {
+            if let Some(dfa) = dfa.as_mut() {
+                if let Some(matched) = dfa.is_match(input, &self.interrupt)? {
+                    return Ok(matched);
+                }
             }
+            // Cache is thrashing, stop using it.
+            *dfa = None;
+        }
 
-            let (next_input, pattern) = Pattern::parse(input, alternation_counter)?;
-            patterns.push(pattern);
-            input = next_input;
+        Ok(self.find_from(input, 0, false)?.is_some())
+    }
+
+    /// Build a matcher to check many lines, with its own DFA cache not shared with other threads.
+    pub fn line_matcher(&self) -> LineMatcher {
+        LineMatcher {
+            regexp: self.clone(),
+            dfa: self.inner.program.as_ref().and_then(LazyDfa::new),
         }
+    }
 
-        if patterns.is_empty() {
-            return Err(GrepError::InvalidPattern);
+    /// Text matched by the regex if it is only made of literal chars, like `foo`.
+    pub fn literal(&self) -> Option<Vec<u8>> {
+        let mut literal = Vec::new();
+        for pattern in self.patterns() {
+            match pattern {
+                Pattern::Literal(c) => literal.extend(c.to_string().as_bytes()),
+                Pattern::Byte(byte) => literal.push(*byte),
+                _ => return None,
+            }
         }
+        Some(literal)
+    }
+
+    /// Find first match in input, which does not need to be valid UTF-8.
+    ///
+    /// Unicode patterns never match invalid UTF-8 sequences, those are only matched by
+    /// byte patterns enabled with `(?-u)`.
+    pub fn find_bytes(&self, input: &[u8]) -> Option<MatchResult> {
+        self.find_bytes_at(input, 0)
+    }
 
-        // Build output
-        if start_string_anchor {
-            patterns.insert(0, Pattern::Start);
+    /// Find first match starting at or after `start`.
+    ///
+    /// Unlike searching in `&input[start..]`, assertions still see text before `start`.
+    pub fn find_bytes_at(&self, input: &[u8], start: usize) -> Option<MatchResult> {
+        self.try_find_bytes_at(input, start).unwrap_or(None)
+    }
+
+    /// Find first match starting at or after `start`, failing if the search is stopped early.
+    pub fn try_find_bytes_at(
+        &self,
+        input: &[u8],
+        start: usize,
+    ) -> Result<Option<MatchResult>, GrepError> {
+        self.find_from(input, start, false)
+    }
+
+    /// Check if every literal required by the regex is in input.
+    fn is_candidate(&self, input: &[u8]) -> bool {
+        self.inner
+            .prefilter
+            .as_ref()
+            .is_none_or(|prefilter| prefilter.is_candidate(input))
+    }
+
+    /// Find first match from `start`, only starting on UTF-8 char boundaries if
+    /// `char_boundaries` is set.
+    fn find_from(
+        &self,
+        input_line: &[u8],
+        start: usize,
+        char_boundaries: bool,
+    ) -> Result<Option<MatchResult>, GrepError> {
+        if !self.is_candidate(input_line) {
+            return Ok(None);
         }
 
-        if end_string_anchor {
-            patterns.push(Pattern::End);
+        // Only backtrack without bounds when the pattern cannot be compiled to an automaton.
+        let prefilter = self.inner.prefilter.as_ref();
+        let interrupt = &self.interrupt;
+        let longest = self.match_kind == MatchKind::LeftmostLongest;
+        let found = match &self.inner.program {
+            Some(program) if backtrack::can_run(program, input_line) => backtrack::find(
+                program,
+                prefilter,
+                input_line,
+                start,
+                char_boundaries,
+                interrupt,
+            ),
+            Some(program) => pikevm::find(
+                program,
+                prefilter,
+                input_line,
+                start,
+                char_boundaries,
+                interrupt,
+            ),
+            None => {
+                let budget = Budget::new(self.match_limit, interrupt);
+                let found = self.backtrack(
+                    input_line,
+                    prefilter,
+                    start,
+                    char_boundaries,
+                    longest,
+                    &budget,
+                );
+                return budget.into_result(found);
+            }
+        }?;
+
+        // Longest match starts where the first one does, only its end needs to be searched.
+        match (found, &self.inner.program) {
+            (Some((start, _)), Some(program)) if longest => {
+                let end = pikevm::longest_end(program, input_line, start, interrupt)?;
+                Ok(end.map(|end| (start, end)))
+            }
+            _ => Ok(found),
         }
+    }
 
-        Ok(Self { patterns })
+    /// Check if the regex needs to be backtracked, not being compiled to an automaton.
+    pub(crate) fn is_backtracked(&self) -> bool {
+        self.inner.program.is_none()
     }
 
-    fn matches(&self, input_line: &str) -> Option<MatchResult> {
-        if self.patterns.first() == Some(&Pattern::Start) {
-            if let Some((res, _table)) =
-                match_here(&self.patterns[1..], MatchContext::new(0, input_line))
-            {
-                return Some(res);
+    fn backtrack(
+        &self,
+        input_line: &[u8],
+        prefilter: Option<&Prefilter>,
+        start: usize,
+        char_boundaries: bool,
+        longest: bool,
+        budget: &Budget,
+    ) -> Option<MatchResult> {
+        let patterns = self.patterns();
+        if patterns.first() == Some(&Pattern::Start) {
+            if start > 0 {
+                return None;
             }
 
-            None
+            match_here(&patterns[1..], input_line, 0, longest, budget)
         } else {
-            for start_idx in 0..input_line.len() {
-                if let Some((res, _table)) =
-                    match_here(&self.patterns, MatchContext::new(start_idx, input_line))
-                {
+            let next_start = |idx: usize| match prefilter {
+                Some(prefilter) => prefilter.next_start(input_line, idx),
+                None => (idx <= input_line.len()).then_some(idx),
+            };
+            let start_indexes = iter::successors(next_start(start), |idx| next_start(idx + 1))
+                .filter(|idx| !char_boundaries || is_char_boundary(input_line, *idx));
+
+            for start_idx in start_indexes {
+                if let Some(res) = match_here(patterns, input_line, start_idx, longest, budget) {
                     return Some(res);
                 }
+                if budget.is_stopped() {
+                    return None;
+                }
             }
 
             None
@@ -124,250 +455,989 @@ impl Regexp {
     }
 }
 
+impl From<Vec<Pattern>> for Regexp {
+    /// Compile patterns, as parsed by [`re_parse`].
+    fn from(patterns: Vec<Pattern>) -> Self {
+        let program = Program::compile(&patterns);
+        let dfa = program.as_ref().and_then(LazyDfa::new);
+
+        Self {
+            inner: Arc::new(Compiled {
+                prefilter: Prefilter::new(&patterns),
+                program,
+                dfa: Mutex::new(dfa),
+                patterns,
+            }),
+            match_kind: MatchKind::default(),
+            match_limit: DEFAULT_MATCH_LIMIT,
+            interrupt: Interrupt::default(),
+        }
+    }
+}
+
+impl fmt::Debug for Regexp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Regexp")
+            .field("patterns", &self.inner.patterns)
+            .finish()
+    }
+}
+
+impl PartialEq for Regexp {
+    fn eq(&self, other: &Self) -> bool {
+        self.inner.patterns == other.inner.patterns
+    }
+}
+
+impl Eq for Regexp {}
+
+/// Check if lines match a regex without reporting where, see [`Regexp::line_matcher`].
+///
+/// A lazy DFA is used when the pattern can be compiled to an automaton, falling back to the
+/// Pike VM if its cache thrashes and to backtracking for back references and lookaround.
+#[derive(Debug, Clone)]
+pub struct LineMatcher {
+    regexp: Regexp,
+    dfa: Option<LazyDfa>,
+}
+
+impl LineMatcher {
+    /// Check if the regex matches anywhere in input, which does not need to be valid UTF-8.
+    pub fn is_match(&mut self, input: &[u8]) -> bool {
+        self.try_is_match(input).unwrap_or(false)
+    }
+
+    /// Check if the regex matches anywhere in input, failing if the search is stopped early.
+    pub fn try_is_match(&mut self, input: &[u8]) -> Result<bool, GrepError> {
+        if !self.regexp.is_candidate(input) {
+            return Ok(false);
+        }
+
+        if let Some(dfa) = &mut self.dfa {
+            match dfa.is_match(input, &self.regexp.interrupt)? {
+                Some(matched) => return Ok(matched),
+                None => self.dfa = None,
+            }
+        }
+
+        Ok(self.regexp.try_find_bytes_at(input, 0)?.is_some())
+    }
+
+    /// Find first match starting at or after `start`, see [`Regexp::find_bytes_at`].
+    pub fn find_at(&self, input: &[u8], start: usize) -> Option<MatchResult> {
+        self.regexp.find_bytes_at(input, start)
+    }
+}
+
 impl Pattern {
-    fn parse<'a>(
-        input: &'a str,
-        alternation_counter: &AtomicUsize,
-    ) -> Result<(&'a str, Self), GrepError> {
-        if let Some(input) = input.strip_prefix(r"\d") {
-            Ok((input, Self::Digit))
-        } else if let Some(input) = input.strip_prefix(r"\w") {
-            Ok((input, Self::Chars))
-        } else if let Some(input) = input.strip_prefix(r"\1") {
-            // NOTE: maybe improve this back reference parsing ...
-            Ok((input, Self::BackReference(1)))
-        } else if let Some(input) = input.strip_prefix(r"\2") {
-            Ok((input, Self::BackReference(2)))
-        } else if let Some(input) = input.strip_prefix(r"\3") {
-            Ok((input, Self::BackReference(3)))
-        } else if let Some(input) = input.strip_prefix(r"\4") {
-            Ok((input, Self::BackReference(4)))
-        } else if let Some(input) = input.strip_prefix(r"\5") {
-            Ok((input, Self::BackReference(5)))
-        } else if let Some(input) = input.strip_prefix('.') {
-            Ok((input, Self::Wildcard))
-        } else if let Some(input) = input.strip_prefix('[') {
-            match input.chars().position(|c| c == ']') {
-                None => Err(GrepError::InvalidPattern),
-                Some(end) => {
-                    let sub_input = &input[..end];
-                    assert!(
-                        !sub_input.contains('['),
-                        "unsupported nested char groups parse"
-                    );
-
-                    let mut chars: Vec<_> = sub_input.chars().collect();
-                    if chars.first().copied() == Some('^') {
-                        Ok((
-                            &input[end + 1..],
-                            Self::NegativeCharGroup(chars.drain(1..).collect()),
-                        ))
-                    } else {
-                        Ok((&input[end + 1..], Self::PositiveCharGroup(chars)))
-                    }
-                }
+    /// Return min and max number of chars this pattern can consume, `None` if unbounded.
+    fn width(&self) -> Option<(usize, usize)> {
+        match self {
+            Self::Literal(_)
+            | Self::Digit
+            | Self::Chars
+            | Self::PositiveCharGroup(_)
+            | Self::NegativeCharGroup(_)
+            | Self::Property { .. }
+            | Self::CharClass { .. }
+            | Self::Byte(_)
+            | Self::ByteClass { .. }
+            | Self::Wildcard
+            | Self::AnyChar => Some((1, 1)),
+            Self::Start
+            | Self::End
+            | Self::EndBeforeNewline
+            | Self::LineStart
+            | Self::LineEnd
+            | Self::ResetStart
+            | Self::LookAhead { .. }
+            | Self::LookBehind { .. } => Some((0, 0)),
+            Self::ZeroOrOne(pattern) | Self::LazyZeroOrOne(pattern) => {
+                pattern.width().map(|(_min, max)| (0, max))
             }
-        } else if let Some(input) = input.strip_prefix('(') {
-            let mut delimiter_count = 1_isize;
-            let mut parse_start = 0;
-            let mut parse_end = 0;
-            let mut sub_inputs = Vec::new();
-
-            // Find end delimiter
-            for (idx, c) in input.chars().enumerate() {
-                match c {
-                    '(' => delimiter_count += 1,
-                    ')' => delimiter_count -= 1,
-                    '|' if delimiter_count == 1 => {
-                        sub_inputs.push(&input[parse_start..idx]);
-                        parse_start = idx + 1;
-                    }
-                    _ => {}
-                }
+            Self::Alternation { alternations, .. } | Self::Group { alternations } => {
+                let mut widths = alternations.iter().map(|alt| sequence_width(alt));
+                let first = widths.next()??;
+                widths.try_fold(first, |(min, max), width| {
+                    let (alt_min, alt_max) = width?;
+                    Some((min.min(alt_min), max.max(alt_max)))
+                })
+            }
+            // Repetition and back reference length are only known at match time.
+            Self::OneOrMore(_) | Self::LazyOneOrMore(_) | Self::BackReference(_) => None,
+        }
+    }
+}
 
-                if delimiter_count == 0 {
-                    sub_inputs.push(&input[parse_start..idx]);
-                    parse_end = idx;
-                    break;
-                }
+impl Pattern {
+    /// Check if pattern matches a single char or byte.
+    pub(crate) fn is_unit(&self) -> bool {
+        matches!(
+            self,
+            Self::Literal(_)
+                | Self::Digit
+                | Self::Chars
+                | Self::PositiveCharGroup(_)
+                | Self::NegativeCharGroup(_)
+                | Self::Property { .. }
+                | Self::CharClass { .. }
+                | Self::Byte(_)
+                | Self::ByteClass { .. }
+                | Self::Wildcard
+                | Self::AnyChar
+        )
+    }
+
+    /// Return number of bytes consumed if unit pattern matches start of input.
+    pub(crate) fn match_unit(&self, input: &[u8]) -> Option<usize> {
+        match self {
+            Self::Byte(byte) => (input.first() == Some(byte)).then_some(1),
+            Self::ByteClass { bytes, negated } => input
+                .first()
+                .filter(|byte| bytes.contains(byte) != *negated)
+                .map(|_| 1),
+            _ => {
+                let c = decode_char(input)?;
+                let matched = match self {
+                    Self::Literal(expected) => c == *expected,
+                    Self::Digit => c.is_ascii_digit(),
+                    Self::Chars => is_word_char(c),
+                    Self::PositiveCharGroup(values) => values.contains(&c),
+                    Self::NegativeCharGroup(values) => !values.contains(&c),
+                    Self::Property { property, negated } => property.contains(c) != *negated,
+                    Self::CharClass { items, negated } => {
+                        items.iter().any(|item| item.matches(c)) != *negated
+                    }
+                    Self::Wildcard => !is_line_terminator(c),
+                    Self::AnyChar => true,
+                    _ => false,
+                };
+                matched.then(|| c.len_utf8())
             }
+        }
+    }
+
+    pub(crate) fn is_anchor(&self) -> bool {
+        matches!(
+            self,
+            Self::Start | Self::End | Self::EndBeforeNewline | Self::LineStart | Self::LineEnd
+        )
+    }
+
+    /// Check if anchor holds at `index` in input.
+    pub(crate) fn match_anchor(&self, input: &[u8], index: usize) -> bool {
+        match self {
+            Self::Start => index == 0,
+            Self::End => index == input.len(),
+            Self::EndBeforeNewline => matches!(&input[index..], b"" | b"\n"),
+            Self::LineStart => index == 0 || input[index - 1] == b'\n',
+            Self::LineEnd => matches!(input.get(index), None | Some(b'\n')),
+            _ => false,
+        }
+    }
+}
 
-            if delimiter_count != 0 {
-                return Err(GrepError::InvalidPattern);
+/// Check position is not in the middle of a UTF-8 encoded char.
+pub(crate) fn is_char_boundary(input: &[u8], index: usize) -> bool {
+    input
+        .get(index)
+        .is_none_or(|byte| byte & 0b1100_0000 != 0b1000_0000)
+}
+
+/// Decode char at start of input, `None` if empty or on invalid UTF-8.
+pub(crate) fn decode_char(input: &[u8]) -> Option<char> {
+    let bytes = &input[..input.len().min(4)];
+    let valid = match str::from_utf8(bytes) {
+        Ok(valid) => valid,
+        Err(e) => str::from_utf8(&bytes[..e.valid_up_to()]).unwrap_or_default(),
+    };
+    valid.chars().next()
+}
+
+/// Add numbers of groups referred to by back references in patterns.
+fn referenced_groups(patterns: &[Pattern], groups: &mut HashSet<usize>) {
+    for pattern in patterns {
+        match pattern {
+            Pattern::BackReference(number) => {
+                groups.insert(*number);
+            }
+            Pattern::OneOrMore(pattern)
+            | Pattern::LazyOneOrMore(pattern)
+            | Pattern::ZeroOrOne(pattern)
+            | Pattern::LazyZeroOrOne(pattern) => {
+                referenced_groups(slice::from_ref(&**pattern), groups)
             }
+            Pattern::Alternation { alternations, .. }
+            | Pattern::Group { alternations }
+            | Pattern::LookAhead { alternations, .. }
+            | Pattern::LookBehind { alternations, .. } => alternations
+                .iter()
+                .for_each(|alternation| referenced_groups(alternation, groups)),
+            _ => {}
+        }
+    }
+}
 
-            let mut alternations = Vec::new();
-            let id = alternation_counter.fetch_add(1, Ordering::Relaxed);
+fn sequence_width(patterns: &[Pattern]) -> Option<(usize, usize)> {
+    patterns.iter().try_fold((0, 0), |(min, max), pattern| {
+        let (pattern_min, pattern_max) = pattern.width()?;
+        Some((min + pattern_min, max + pattern_max))
+    })
+}
 
-            for sub_sequence in sub_inputs {
-                let sub_re = Regexp::parse(sub_sequence, alternation_counter)?;
-                alternations.push(sub_re.patterns);
+impl Flags {
+    fn byte_class(self, bytes: Vec<u8>, negated: bool) -> Pattern {
+        let mut class = Vec::new();
+        for byte in bytes {
+            for variant in self.case_variants(char::from(byte)) {
+                // Case variant of an ASCII char may not be ASCII, like Kelvin sign for `k`.
+                if variant.is_ascii() && !class.contains(&(variant as u8)) {
+                    class.push(variant as u8);
+                }
             }
+        }
+        Pattern::ByteClass {
+            bytes: class,
+            negated,
+        }
+    }
+
+    fn literal(self, c: char) -> Pattern {
+        let mut variants = self.case_variants(c);
+        // Like in bracket expressions, ASCII chars only have ASCII case variants in bytes mode.
+        if self.bytes && c.is_ascii() {
+            variants.retain(char::is_ascii);
+        }
+        if variants.len() > 1 {
+            Pattern::PositiveCharGroup(variants)
+        } else {
+            Pattern::Literal(c)
+        }
+    }
 
-            Ok((
-                &input[parse_end + 1..],
-                Self::Alternation { alternations, id },
-            ))
-        } else if input.is_empty() {
-            Err(GrepError::InvalidPattern)
+    fn case_variants(self, c: char) -> Vec<char> {
+        if self.case_insensitive {
+            unicode::case_orbit(c)
         } else {
-            let (val, input) = input.split_at(1);
-            assert_eq!(val.len(), 1);
+            vec![c]
+        }
+    }
 
-            Ok((
-                input,
-                Self::Literal(val.chars().next().expect("split at fail")),
-            ))
+    fn property(self, property: UnicodeProperty) -> UnicodeProperty {
+        if self.case_insensitive {
+            property.case_insensitive()
+        } else {
+            property
         }
     }
 }
 
-fn match_here<'a>(
+/// Find first match of patterns starting at `start_index`, or the longest one if `longest`
+/// is set, backtracking with an explicit stack of choice points so long inputs cannot
+/// overflow the thread stack.
+fn match_here(
     patterns: &[Pattern],
-    context: MatchContext<'a>,
-) -> Option<(MatchResult, ReferenceTable<'a>)> {
-    match (context.first_char(), patterns.split_first()) {
-        // Check if pattern and current char match
-        (Some(input_char), Some((Pattern::Literal(char), rem_patterns))) if input_char == *char => {
-            match_here(rem_patterns, context.next_char())
-        }
-        (Some(input_char), Some((Pattern::Digit, rem_patterns))) if input_char.is_ascii_digit() => {
-            match_here(rem_patterns, context.next_char())
-        }
-        (Some(input_char), Some((Pattern::Chars, rem_patterns)))
-            if input_char.is_alphanumeric() =>
-        {
-            match_here(rem_patterns, context.next_char())
-        }
-        (Some(input_char), Some((Pattern::PositiveCharGroup(values), rem_patterns)))
-            if values.contains(&input_char) =>
-        {
-            match_here(rem_patterns, context.next_char())
-        }
-        (Some(input_char), Some((Pattern::NegativeCharGroup(values), rem_patterns)))
-            if !values.contains(&input_char) =>
-        {
-            match_here(rem_patterns, context.next_char())
-        }
-        (Some(_), Some((Pattern::Wildcard, rem_patterns))) => {
-            match_here(rem_patterns, context.next_char())
-        }
-        // Match back reference
-        (_, Some((Pattern::BackReference(index), rem_patterns))) => {
-            let reference = context.back_references.get(index)?;
-            if !context.input_line[context.current_index..].starts_with(reference) {
-                return None;
+    input: &[u8],
+    start_index: usize,
+    longest: bool,
+    budget: &Budget,
+) -> Option<MatchResult> {
+    let mut search = Search {
+        input,
+        longest,
+        longest_match: None,
+        budget,
+        continuations: Vec::new(),
+        levels: Vec::new(),
+        choices: Vec::new(),
+        explored: HashSet::new(),
+        referenced: HashSet::new(),
+    };
+    if longest {
+        referenced_groups(patterns, &mut search.referenced);
+    }
+
+    search.run(Thread {
+        patterns,
+        next: None,
+        index: start_index,
+        start: start_index,
+        back_references: HashMap::new(),
+    })
+}
+
+/// Patterns to match once the current ones are, linked to the ones after them.
+struct Continuation<'p> {
+    patterns: &'p [Pattern],
+    next: Option<usize>,
+    /// Repetition to try again before `patterns`.
+    repetition: Option<Repetition<'p>>,
+}
+
+/// Repetition which matched an iteration, starting at `start_index`.
+#[derive(Clone, Copy)]
+struct Repetition<'p> {
+    inner: &'p Pattern,
+    lazy: bool,
+    start_index: usize,
+    /// Iteration is the first one, which may match nothing.
+    first: bool,
+}
+
+/// Position of a backtracking search.
+#[derive(Debug, Clone)]
+struct Thread<'p, 'a> {
+    patterns: &'p [Pattern],
+    /// Continuation to match after `patterns`.
+    next: Option<usize>,
+    index: usize,
+    /// Reported start of the match, moved by `\K`.
+    start: usize,
+    back_references: ReferenceTable<'a>,
+}
+
+/// Nested search of a group or lookaround, at its current alternative.
+#[derive(Debug, Clone, Copy)]
+enum SubSearch<'p> {
+    /// Alternative matched from cursor, backtracked into when the remaining patterns fail.
+    Alternation {
+        alternations: &'p [Vec<Pattern>],
+        id: Option<usize>,
+        alt: usize,
+    },
+    LookAhead {
+        alternations: &'p [Vec<Pattern>],
+        negative: bool,
+        alt: usize,
+    },
+    /// Alternative matched from `start_index` to exactly the cursor, `None` being the
+    /// furthest start allowed by its width.
+    LookBehind {
+        alternations: &'p [Vec<Pattern>],
+        negative: bool,
+        alt: usize,
+        start_index: Option<usize>,
+    },
+}
+
+impl SubSearch<'_> {
+    fn is_negative(&self) -> bool {
+        match self {
+            Self::Alternation { .. } => false,
+            Self::LookAhead { negative, .. } | Self::LookBehind { negative, .. } => *negative,
+        }
+    }
+}
+
+/// Running nested search, lookaround only keeping its first match.
+struct Level<'p, 'a> {
+    search: SubSearch<'p>,
+    input: &'a [u8],
+    start_index: usize,
+    end_index: usize,
+    /// Enclosing search position, resumed after the group or lookaround.
+    outer: Thread<'p, 'a>,
+    choice_count: usize,
+    continuation_count: usize,
+}
+
+enum Choice<'p, 'a> {
+    /// Resume search from a saved position.
+    Resume {
+        thread: Thread<'p, 'a>,
+        continuation_count: usize,
+    },
+    /// Resume a matched group, the remaining patterns failing after it.
+    Reenter(Level<'p, 'a>),
+}
+
+struct Search<'p, 'a, 'b> {
+    input: &'a [u8],
+    /// Keep backtracking after a match, to find the longest one.
+    longest: bool,
+    longest_match: Option<MatchResult>,
+    budget: &'b Budget<'b>,
+    continuations: Vec<Continuation<'p>>,
+    /// Nested searches, innermost last.
+    levels: Vec<Level<'p, 'a>>,
+    choices: Vec<Choice<'p, 'a>>,
+    /// Keys of states already explored by a leftmost-longest search, see [`Search::state_key`].
+    explored: HashSet<Vec<usize>>,
+    /// Groups referred to by back references, whose captures make states different.
+    referenced: HashSet<usize>,
+}
+
+/// Pattern checking lookbehind alternatives end exactly at the cursor.
+const LOOK_BEHIND_END: &[Pattern] = &[Pattern::End];
+
+impl<'p, 'a> Search<'p, 'a, '_> {
+    fn run(mut self, mut thread: Thread<'p, 'a>) -> Option<MatchResult> {
+        loop {
+            let next_thread = match thread.patterns.split_first() {
+                Some(_) if !self.budget.step() => return None,
+                Some((pattern, rem_patterns)) => self.step(pattern, rem_patterns, thread),
+                // Matches from a state already explored cannot be longer than the longest one.
+                None if thread.next.is_some() && !self.explore(&thread) => None,
+                None => match thread.next {
+                    Some(next) => {
+                        let continuation = &self.continuations[next];
+                        thread.patterns = continuation.patterns;
+                        thread.next = continuation.next;
+                        match continuation.repetition {
+                            Some(repetition) => self.repeat(repetition, thread),
+                            None => Some(thread),
+                        }
+                    }
+                    None if self.levels.is_empty() && !self.longest => {
+                        return Some((thread.start, thread.index));
+                    }
+                    None if self.levels.is_empty() => {
+                        if self.longest_match.is_none_or(|(_, end)| thread.index > end) {
+                            self.longest_match = Some((thread.start, thread.index));
+                        }
+                        // No match can be longer than one ending with the input.
+                        if thread.index == self.input.len() {
+                            return self.longest_match;
+                        }
+                        None
+                    }
+                    None => self.succeed(thread),
+                },
+            };
+
+            thread = match next_thread {
+                Some(next_thread) => next_thread,
+                None => match self.backtrack() {
+                    Some(thread) => thread,
+                    None => return self.longest_match,
+                },
+            };
+        }
+    }
+
+    /// Check if a leftmost-longest search has not explored the state of `thread` yet,
+    /// remembering it.
+    ///
+    /// States in lookaround are not remembered, as it stops at its first match.
+    fn explore(&mut self, thread: &Thread<'p, 'a>) -> bool {
+        let in_look_around = self
+            .levels
+            .iter()
+            .any(|level| !matches!(level.search, SubSearch::Alternation { .. }));
+        if !self.longest || in_look_around || self.explored.len() >= MAX_EXPLORED {
+            return true;
+        }
+        let key = self.state_key(thread);
+        self.explored.insert(key)
+    }
+
+    /// Encode everything matches from `thread` depend on, except the reported start which
+    /// does not make them longer.
+    fn state_key(&self, thread: &Thread<'p, 'a>) -> Vec<usize> {
+        let mut key = Vec::new();
+        self.thread_key(thread, &mut key);
+        for level in self.levels.iter().rev() {
+            if let SubSearch::Alternation {
+                alternations,
+                id,
+                alt,
+            } = level.search
+            {
+                key.extend([
+                    alternations.as_ptr() as usize,
+                    id.unwrap_or(0),
+                    alt,
+                    level.start_index,
+                    level.end_index,
+                ]);
+            }
+            self.thread_key(&level.outer, &mut key);
+        }
+        key
+    }
+
+    fn thread_key(&self, thread: &Thread<'p, 'a>, key: &mut Vec<usize>) {
+        key.extend([
+            thread.patterns.as_ptr() as usize,
+            thread.patterns.len(),
+            thread.index,
+        ]);
+
+        let mut references: Vec<_> = thread
+            .back_references
+            .iter()
+            .filter(|(id, _)| self.referenced.contains(id))
+            .map(|(id, reference)| [*id, reference.as_ptr() as usize, reference.len()])
+            .collect();
+        references.sort_unstable();
+        key.push(references.len());
+        key.extend(references.into_iter().flatten());
+
+        let mut next = thread.next;
+        while let Some(idx) = next {
+            let continuation = &self.continuations[idx];
+            key.extend([
+                continuation.patterns.as_ptr() as usize,
+                continuation.patterns.len(),
+            ]);
+            match continuation.repetition {
+                Some(repetition) => key.extend([
+                    1,
+                    repetition.inner as *const Pattern as usize,
+                    usize::from(repetition.lazy),
+                    repetition.start_index,
+                    usize::from(repetition.first),
+                ]),
+                None => key.push(0),
+            }
+            next = continuation.next;
+        }
+        key.push(usize::MAX);
+    }
+
+    /// Input and end index of the innermost search.
+    fn bounds(&self) -> (&'a [u8], usize) {
+        match self.levels.last() {
+            Some(level) => (level.input, level.end_index),
+            None => (self.input, self.input.len()),
+        }
+    }
+
+    /// Match first pattern, returning `None` to backtrack.
+    fn step(
+        &mut self,
+        pattern: &'p Pattern,
+        rem_patterns: &'p [Pattern],
+        mut thread: Thread<'p, 'a>,
+    ) -> Option<Thread<'p, 'a>> {
+        let (input, end_index) = self.bounds();
+
+        match pattern {
+            // Check if pattern and current char match
+            pattern if pattern.is_unit() => {
+                let remaining = &input[thread.index..end_index.max(thread.index)];
+                thread.index += pattern.match_unit(remaining)?;
+                thread.patterns = rem_patterns;
+                Some(thread)
+            }
+            // Match anchors
+            pattern if pattern.is_anchor() => {
+                if !pattern.match_anchor(input, thread.index) {
+                    return None;
+                }
+                thread.patterns = rem_patterns;
+                Some(thread)
+            }
+            // Match back reference
+            Pattern::BackReference(index) => {
+                let reference = thread.back_references.get(index)?;
+                if !input[thread.index..].starts_with(reference) {
+                    return None;
+                }
+                thread.index += reference.len();
+                thread.patterns = rem_patterns;
+                Some(thread)
+            }
+            // Match inner pattern once, then see if it repeats
+            Pattern::OneOrMore(inner) | Pattern::LazyOneOrMore(inner) => {
+                let repetition = Repetition {
+                    inner,
+                    lazy: matches!(pattern, Pattern::LazyOneOrMore(_)),
+                    start_index: thread.index,
+                    first: true,
+                };
+                thread.next = Some(self.push_repetition(rem_patterns, thread.next, repetition));
+                thread.patterns = slice::from_ref(inner);
+                Some(thread)
+            }
+            // Match inner pattern, or skip it
+            Pattern::ZeroOrOne(inner) => {
+                self.push_choice(Thread {
+                    patterns: rem_patterns,
+                    ..thread.clone()
+                });
+
+                thread.next = Some(self.push_continuation(rem_patterns, thread.next));
+                thread.patterns = slice::from_ref(inner);
+                Some(thread)
+            }
+            // Skip inner pattern, or match it
+            Pattern::LazyZeroOrOne(inner) => {
+                let next = self.push_continuation(rem_patterns, thread.next);
+                self.push_choice(Thread {
+                    patterns: slice::from_ref(inner),
+                    next: Some(next),
+                    ..thread.clone()
+                });
+
+                thread.patterns = rem_patterns;
+                Some(thread)
+            }
+            Pattern::ResetStart => {
+                thread.start = thread.index;
+                thread.patterns = rem_patterns;
+                Some(thread)
+            }
+            Pattern::Alternation { alternations, id } => {
+                let search = SubSearch::Alternation {
+                    alternations,
+                    id: Some(*id),
+                    alt: 0,
+                };
+                self.start(search, rem_patterns, thread)
+            }
+            Pattern::Group { alternations } => {
+                let search = SubSearch::Alternation {
+                    alternations,
+                    id: None,
+                    alt: 0,
+                };
+                self.start(search, rem_patterns, thread)
+            }
+            Pattern::LookAhead {
+                alternations,
+                negative,
+            } => {
+                let search = SubSearch::LookAhead {
+                    alternations,
+                    negative: *negative,
+                    alt: 0,
+                };
+                self.start(search, rem_patterns, thread)
             }
-            match_here(rem_patterns, context.nth_char(reference.len()))
-        }
-        // Match multiple chars
-        (_, Some((Pattern::OneOrMore(pattern), rem_patterns))) => {
-            // Match inner pattern with input patterns = match more
-            match_here(&concat_pattern(pattern, patterns), context.clone()).or_else(||
-                // Match inner pattern with remaining patterns = match one
-                match_here(&concat_pattern(pattern, rem_patterns), context))
-        }
-        (_, Some((Pattern::ZeroOrOne(pattern), rem_patterns))) => {
-            // Match one
-            match_here(&concat_pattern(pattern, rem_patterns), context.clone()).or_else(||
-                // Match zero
-                match_here(rem_patterns, context))
-        }
-        (_, Some((Pattern::Alternation { alternations, id }, rem_patterns))) => {
-            // For each possible alternation.
-            for alt in alternations {
-                // Check with line shorter than the whole input there is a negative char group in alternation.
-                for end_index in (context.current_index..=context.input_line.len()).rev() {
-                    // Create a new standalone context.
-                    if let Some((alt_match, alt_ref_table)) = match_here(
-                        alt,
-                        MatchContext::new(context.current_index, &context.input_line[..end_index]),
-                    ) {
-                        // If alternation has match, merge everything output from result into current context.
-                        let mut next_context = context
-                            .nth_char(alt_match.1 - alt_match.0)
-                            .with_back_reference(*id, alt_match);
-
-                        next_context.back_references.extend(alt_ref_table.iter());
-
-                        // Then try to match remaining patterns.
-                        if let Some(((_, end_index), ref_table)) =
-                            match_here(rem_patterns, next_context)
-                        {
-                            return Some(((context.start_index, end_index), ref_table));
+            Pattern::LookBehind {
+                alternations,
+                negative,
+            } => {
+                let search = SubSearch::LookBehind {
+                    alternations,
+                    negative: *negative,
+                    alt: 0,
+                    start_index: None,
+                };
+                self.start(search, rem_patterns, thread)
+            }
+            // It there is some pattern left and it did not match whatever char we have
+            _ => None,
+        }
+    }
+
+    /// Match another iteration of the repetition, or the patterns after it.
+    ///
+    /// Like in compiled programs, where the same instruction is not visited twice at the same
+    /// position, a first iteration matching nothing ends the repetition, and later ones fail.
+    fn repeat(
+        &mut self,
+        repetition: Repetition<'p>,
+        mut thread: Thread<'p, 'a>,
+    ) -> Option<Thread<'p, 'a>> {
+        if thread.index == repetition.start_index {
+            return repetition.first.then_some(thread);
+        }
+
+        let again = Repetition {
+            start_index: thread.index,
+            first: false,
+            ..repetition
+        };
+        let again = self.push_repetition(thread.patterns, thread.next, again);
+        let inner = slice::from_ref(repetition.inner);
+
+        if repetition.lazy {
+            self.push_choice(Thread {
+                patterns: inner,
+                next: Some(again),
+                ..thread.clone()
+            });
+        } else {
+            self.push_choice(thread.clone());
+            thread.patterns = inner;
+            thread.next = Some(again);
+        }
+        Some(thread)
+    }
+
+    /// Start nested search, the outer one resuming with `rem_patterns` after it.
+    fn start(
+        &mut self,
+        search: SubSearch<'p>,
+        rem_patterns: &'p [Pattern],
+        thread: Thread<'p, 'a>,
+    ) -> Option<Thread<'p, 'a>> {
+        let outer = Thread {
+            patterns: rem_patterns,
+            ..thread
+        };
+        self.enter(search, outer)
+    }
+
+    /// Enter first valid alternative at or after `search`, handling exhausted searches.
+    fn enter(&mut self, search: SubSearch<'p>, outer: Thread<'p, 'a>) -> Option<Thread<'p, 'a>> {
+        let (input, end_index) = self.bounds();
+        let Some(search) = self.seek(search, &outer) else {
+            // Nothing matched, which is what a negative lookaround expects.
+            return search.is_negative().then_some(outer);
+        };
+
+        let (level_input, start_index, end_index, patterns, next) = match search {
+            SubSearch::Alternation {
+                alternations, alt, ..
+            } => (input, outer.index, end_index, &alternations[alt], None),
+            SubSearch::LookAhead {
+                alternations, alt, ..
+            } => (input, outer.index, input.len(), &alternations[alt], None),
+            SubSearch::LookBehind {
+                alternations,
+                alt,
+                start_index,
+                ..
+            } => {
+                let before = &input[..outer.index];
+                let end = self.push_continuation(LOOK_BEHIND_END, None);
+                let start_index = start_index.unwrap_or(before.len());
+                (
+                    before,
+                    start_index,
+                    before.len(),
+                    &alternations[alt],
+                    Some(end),
+                )
+            }
+        };
+
+        // Nested patterns can refer to groups captured before them.
+        let start = outer.start;
+        let back_references = outer.back_references.clone();
+        self.levels.push(Level {
+            search,
+            input: level_input,
+            start_index,
+            end_index,
+            outer,
+            choice_count: self.choices.len(),
+            continuation_count: self.continuations.len(),
+        });
+
+        Some(Thread {
+            patterns,
+            next,
+            index: start_index,
+            start,
+            back_references,
+        })
+    }
+
+    /// First valid alternative at or after `search`.
+    fn seek(&self, mut search: SubSearch<'p>, outer: &Thread<'p, 'a>) -> Option<SubSearch<'p>> {
+        loop {
+            match &mut search {
+                SubSearch::Alternation {
+                    alternations, alt, ..
+                }
+                | SubSearch::LookAhead {
+                    alternations, alt, ..
+                } => return (*alt < alternations.len()).then_some(search),
+                SubSearch::LookBehind {
+                    alternations,
+                    alt,
+                    start_index,
+                    ..
+                } => {
+                    let sequence = alternations.get(*alt)?;
+
+                    // Width is counted in chars, each one being up to 4 bytes long.
+                    let starts = sequence_width(sequence).and_then(|(min, max)| {
+                        let first = outer.index.saturating_sub(max.saturating_mul(4));
+                        let last = outer.index.checked_sub(min)?;
+                        (first <= last).then_some((first, last))
+                    });
+
+                    match (starts, *start_index) {
+                        (Some((_, last)), None) => {
+                            *start_index = Some(last);
+                            return Some(search);
+                        }
+                        (Some((first, _)), Some(index)) if index >= first => return Some(search),
+                        _ => {
+                            *alt += 1;
+                            *start_index = None;
                         }
                     }
                 }
             }
+        }
+    }
 
-            None
+    /// Alternative after `search`, which may not be valid.
+    fn advance(mut search: SubSearch<'p>) -> SubSearch<'p> {
+        match &mut search {
+            SubSearch::Alternation { alt, .. } | SubSearch::LookAhead { alt, .. } => *alt += 1,
+            SubSearch::LookBehind {
+                alt, start_index, ..
+            } => match start_index.and_then(|index| index.checked_sub(1)) {
+                Some(index) => *start_index = Some(index),
+                None => {
+                    *alt += 1;
+                    *start_index = None;
+                }
+            },
         }
-        // Check end pattern
-        (None, Some((Pattern::End, _rem_patterns))) => Some((
-            (context.start_index, context.current_index),
-            context.back_references,
-        )),
-        // If there is no more pattern
-        (_, None) => Some((
-            (context.start_index, context.current_index),
-            context.back_references,
-        )),
-        // // It there is some pattern left and it did not match whatever char we have
-        (_, Some(_)) => None,
+        search
+    }
+
+    /// Nested search matched, resume outer search after it.
+    fn succeed(&mut self, thread: Thread<'p, 'a>) -> Option<Thread<'p, 'a>> {
+        let level = self.levels.pop()?;
+
+        if let SubSearch::Alternation { id, .. } = level.search {
+            // Choices of the group are kept, to be tried if the remaining patterns fail.
+            // Captured after the nested ones, which include the group's previous iteration.
+            let mut outer = level.outer.clone();
+            outer.back_references.extend(thread.back_references);
+            if let Some(id) = id {
+                let reference = &level.input[level.start_index..thread.index];
+                outer.back_references.insert(id, reference);
+            }
+            outer.index = thread.index;
+            outer.start = thread.start;
+
+            self.choices.push(Choice::Reenter(level));
+            return Some(outer);
+        }
+
+        // Lookaround only keeps its first match.
+        self.choices.truncate(level.choice_count);
+        self.continuations.truncate(level.continuation_count);
+        if level.search.is_negative() {
+            return None;
+        }
+
+        let mut outer = level.outer;
+        outer.back_references.extend(thread.back_references);
+        Some(outer)
+    }
+
+    /// Restore last choice point, `None` if there is none left.
+    fn backtrack(&mut self) -> Option<Thread<'p, 'a>> {
+        loop {
+            // Nested search has no choice left, so it failed.
+            let choice_count = self.levels.last().map_or(0, |level| level.choice_count);
+            if self.choices.len() == choice_count {
+                let level = self.levels.pop()?;
+                self.continuations.truncate(level.continuation_count);
+
+                let search = Self::advance(level.search);
+                if let Some(thread) = self.enter(search, level.outer) {
+                    return Some(thread);
+                }
+                continue;
+            }
+
+            match self.choices.pop()? {
+                Choice::Resume {
+                    thread,
+                    continuation_count,
+                } => {
+                    self.continuations.truncate(continuation_count);
+                    return Some(thread);
+                }
+                Choice::Reenter(level) => self.levels.push(level),
+            }
+        }
+    }
+
+    fn push_continuation(&mut self, patterns: &'p [Pattern], next: Option<usize>) -> usize {
+        self.continuations.push(Continuation {
+            patterns,
+            next,
+            repetition: None,
+        });
+        self.continuations.len() - 1
+    }
+
+    fn push_repetition(
+        &mut self,
+        patterns: &'p [Pattern],
+        next: Option<usize>,
+        repetition: Repetition<'p>,
+    ) -> usize {
+        self.continuations.push(Continuation {
+            patterns,
+            next,
+            repetition: Some(repetition),
+        });
+        self.continuations.len() - 1
+    }
+
+    fn push_choice(&mut self, thread: Thread<'p, 'a>) {
+        self.choices.push(Choice::Resume {
+            thread,
+            continuation_count: self.continuations.len(),
+        });
     }
 }
 
-fn concat_pattern(item: &Pattern, items: &[Pattern]) -> Vec<Pattern> {
-    let mut output = Vec::with_capacity(items.len() + 1);
-    output.push(item.clone());
-    output.extend(items.iter().cloned());
-    output
+/// Check if char is matched by `\w`, like in GNU grep and PCRE.
+pub(crate) fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
 }
 
-#[derive(Debug, Clone, Default)]
-struct MatchContext<'a> {
-    start_index: usize,
-    current_index: usize,
-    input_line: &'a str,
-    back_references: ReferenceTable<'a>,
+/// Chars `.` does not match unless dot-all mode is enabled.
+fn is_line_terminator(c: char) -> bool {
+    matches!(c, '\n' | '\r')
 }
 
-impl<'a> MatchContext<'a> {
-    #[inline(always)]
-    fn new(start_index: usize, input_line: &'a str) -> Self {
+/// Number of steps a backtracking search can still take, shared by every nested match.
+#[derive(Debug)]
+struct Budget<'a> {
+    remaining: Cell<usize>,
+    interrupt: &'a Interrupt,
+    /// Why the search was stopped.
+    error: RefCell<Option<GrepError>>,
+}
+
+impl<'a> Budget<'a> {
+    fn new(limit: usize, interrupt: &'a Interrupt) -> Self {
         Self {
-            start_index,
-            current_index: start_index,
-            input_line,
-            back_references: HashMap::new(),
+            remaining: Cell::new(limit),
+            interrupt,
+            error: RefCell::new(None),
         }
     }
 
-    #[inline(always)]
-    fn next_char(&self) -> Self {
-        self.nth_char(1)
-    }
+    /// Take a step, returning `false` once the budget is exhausted or the search interrupted.
+    fn step(&self) -> bool {
+        if self.is_stopped() {
+            return false;
+        }
 
-    #[inline(always)]
-    fn nth_char(&self, count: usize) -> Self {
-        Self {
-            start_index: self.start_index,
-            current_index: self.current_index + count,
-            input_line: self.input_line,
-            back_references: self.back_references.clone(),
+        let Some(remaining) = self.remaining.get().checked_sub(1) else {
+            self.stop(GrepError::MatchLimitExceeded);
+            return false;
+        };
+        self.remaining.set(remaining);
+
+        if remaining.is_multiple_of(CHECK_INTERVAL) {
+            if let Err(e) = self.interrupt.check() {
+                self.stop(e);
+                return false;
+            }
         }
+        true
     }
 
-    #[inline(always)]
-    fn with_back_reference(mut self, id: usize, pos: MatchResult) -> Self {
-        self.back_references
-            .insert(id, &self.input_line[pos.0..pos.1]);
+    fn stop(&self, error: GrepError) {
+        *self.error.borrow_mut() = Some(error);
+    }
 
-        self
+    fn is_stopped(&self) -> bool {
+        self.error.borrow().is_some()
     }
 
-    #[inline(always)]
-    fn first_char(&self) -> Option<char> {
-        self.input_line.chars().nth(self.current_index)
+    fn into_result(self, found: Option<MatchResult>) -> Result<Option<MatchResult>, GrepError> {
+        match self.error.into_inner() {
+            Some(e) => Err(e),
+            None => Ok(found),
+        }
     }
 }
@@ -0,0 +1,172 @@
+use std::mem::size_of;
+
+use crate::Pattern;
+
+/// Literal text every match must contain, used to skip input before running a matcher.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Prefilter {
+    /// Text every match starts with.
+    prefix: Option<Finder>,
+    /// Text every match contains, longest literal of the pattern and suffix.
+    required: Vec<Finder>,
+}
+
+impl Prefilter {
+    /// Extract literals from patterns, `None` if there is no useful one.
+    pub(crate) fn new(patterns: &[Pattern]) -> Option<Self> {
+        let mut pieces = Vec::new();
+        patterns
+            .iter()
+            .for_each(|pattern| literal_pieces(pattern, &mut pieces));
+
+        let runs: Vec<Vec<u8>> = pieces
+            .split(Option::is_none)
+            .map(|run| run.iter().flatten().flatten().copied().collect())
+            .collect();
+
+        let prefix = runs.first().cloned().unwrap_or_default();
+        let suffix = runs.last().cloned().unwrap_or_default();
+        let inner = runs
+            .iter()
+            .max_by_key(|run| run.len())
+            .cloned()
+            .unwrap_or_default();
+
+        let mut required = Vec::new();
+        for literal in [inner, suffix] {
+            if !literal.is_empty() && required.iter().all(|f: &Finder| f.needle != literal) {
+                required.push(Finder::new(literal));
+            }
+        }
+
+        let prefix = (!prefix.is_empty()).then(|| Finder::new(prefix));
+        (prefix.is_some() || !required.is_empty()).then_some(Self { prefix, required })
+    }
+
+    /// Check if every required literal is in input, otherwise there cannot be any match.
+    pub(crate) fn is_candidate(&self, input: &[u8]) -> bool {
+        self.required
+            .iter()
+            .all(|finder| finder.find(input, 0).is_some())
+    }
+
+    /// Check if a match could start at `index`.
+    pub(crate) fn is_start(&self, input: &[u8], index: usize) -> bool {
+        self.prefix
+            .as_ref()
+            .is_none_or(|prefix| input[index..].starts_with(&prefix.needle))
+    }
+
+    /// Find next position from `index` where a match could start.
+    pub(crate) fn next_start(&self, input: &[u8], index: usize) -> Option<usize> {
+        match &self.prefix {
+            Some(prefix) => prefix.find(input, index),
+            None => (index <= input.len()).then_some(index),
+        }
+    }
+}
+
+/// Flatten pattern in literal text pieces, `None` standing for text that is not known.
+///
+/// Zero width patterns do not add anything as they do not split literals around them.
+fn literal_pieces(pattern: &Pattern, output: &mut Vec<Option<Vec<u8>>>) {
+    match pattern {
+        Pattern::Literal(c) => output.push(Some(c.to_string().into_bytes())),
+        Pattern::Byte(byte) => output.push(Some(vec![*byte])),
+        Pattern::OneOrMore(pattern) | Pattern::LazyOneOrMore(pattern) => {
+            literal_pieces(pattern, output);
+            output.push(None);
+        }
+        Pattern::Alternation { alternations, .. } | Pattern::Group { alternations }
+            if alternations.len() == 1 =>
+        {
+            alternations[0]
+                .iter()
+                .for_each(|pattern| literal_pieces(pattern, output));
+        }
+        pattern if pattern.width() == Some((0, 0)) => {}
+        _ => output.push(None),
+    }
+}
+
+/// Substring search, scanning a word at a time for the rarest byte of the needle.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Finder {
+    needle: Vec<u8>,
+    /// Index in needle of the byte to scan for.
+    rare_index: usize,
+}
+
+impl Finder {
+    pub(crate) fn new(needle: Vec<u8>) -> Self {
+        let rare_index = needle
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, byte)| byte_frequency(**byte))
+            .map_or(0, |(idx, _)| idx);
+
+        Self { needle, rare_index }
+    }
+
+    /// Find first occurrence of needle starting at or after `index`.
+    pub(crate) fn find(&self, haystack: &[u8], index: usize) -> Option<usize> {
+        if self.needle.is_empty() {
+            return (index <= haystack.len()).then_some(index);
+        }
+
+        let rare_byte = self.needle[self.rare_index];
+        let mut candidate = index;
+
+        while candidate + self.needle.len() <= haystack.len() {
+            let scan_start = candidate + self.rare_index;
+            let found = memchr(rare_byte, &haystack[scan_start..])?;
+            candidate = scan_start + found - self.rare_index;
+
+            if haystack[candidate..].starts_with(&self.needle) {
+                return Some(candidate);
+            }
+            candidate += 1;
+        }
+
+        None
+    }
+}
+
+/// Rough frequency of bytes in text, lower is rarer.
+fn byte_frequency(byte: u8) -> u8 {
+    match byte {
+        b' ' | b'a'..=b'z' => 2,
+        b'A'..=b'Z' | b'0'..=b'9' => 1,
+        _ => 0,
+    }
+}
+
+/// Find first position of `needle` byte, checking a whole word at once.
+fn memchr(needle: u8, haystack: &[u8]) -> Option<usize> {
+    const WORD: usize = size_of::<usize>();
+    const LOW_BITS: usize = usize::MAX / 255;
+    const HIGH_BITS: usize = LOW_BITS << 7;
+
+    let repeated = LOW_BITS * needle as usize;
+    let mut chunks = haystack.chunks_exact(WORD);
+
+    for (idx, chunk) in chunks.by_ref().enumerate() {
+        let mut word = [0; WORD];
+        word.copy_from_slice(chunk);
+
+        // Some byte is zero once xored if it is equal to needle.
+        let word = usize::from_ne_bytes(word) ^ repeated;
+        if word.wrapping_sub(LOW_BITS) & !word & HIGH_BITS != 0 {
+            if let Some(pos) = chunk.iter().position(|byte| *byte == needle) {
+                return Some(idx * WORD + pos);
+            }
+        }
+    }
+
+    let offset = haystack.len() - chunks.remainder().len();
+    chunks
+        .remainder()
+        .iter()
+        .position(|byte| *byte == needle)
+        .map(|pos| offset + pos)
+}
@@ -1,22 +1,376 @@
-use std::{env, io, process};
+mod cli;
+
+use std::{
+    cmp::Reverse,
+    env,
+    fs::File,
+    io::{self, BufRead, BufReader, IsTerminal, Write},
+    iter, process,
+    time::Instant,
+};
 
 use grep_starter_rust::*;
 
-// Usage: echo <input_text> | your_grep.sh -E <pattern>
+use crate::cli::{Args, ColorChoice, OutputMode, Syntax};
+
+/// Escape sequences highlighting matches, same as GNU grep.
+const MATCH_COLOR: &str = "\x1b[01;31m\x1b[K";
+const RESET_COLOR: &str = "\x1b[m\x1b[K";
+
+// Usage: echo <input_text> | your_grep.sh [-E|-F|-G|-P] <pattern> [FILE]...
 fn main() {
-    if env::args().nth(1).unwrap() != "-E" {
-        eprintln!("Expected first argument to be '-E'");
-        process::exit(1);
+    let args = match Args::parse(env::args().skip(1)) {
+        Ok(args) => args,
+        Err(message) => {
+            eprintln!("{message}");
+            process::exit(1);
+        }
+    };
+    let deadline = args.timeout.map(|timeout| Instant::now() + timeout);
+
+    // Parse every pattern before reading input so errors are reported upfront.
+    let mut regexps = Vec::new();
+    if !args.fixed_strings {
+        regexps = parse_regexps(&args, false, deadline);
     }
 
-    let pattern = env::args().nth(2).unwrap();
-    let mut input_line = String::new();
+    // Many plain patterns are searched at once, only ignoring case of ASCII chars.
+    let literals: Option<Vec<Vec<u8>>> = if args.fixed_strings {
+        let ascii = !args.flags.case_insensitive || args.patterns.iter().all(|p| p.is_ascii());
+        ascii.then(|| {
+            args.patterns
+                .iter()
+                .map(|p| p.as_bytes().to_vec())
+                .collect()
+        })
+    } else {
+        regexps.iter().map(Regexp::literal).collect()
+    };
+    let literals = literals.filter(|literals| literals.len() > 1);
 
-    io::stdin().read_line(&mut input_line).unwrap();
+    if literals.is_none() && !args.fixed_strings && args.whole_word {
+        regexps = parse_regexps(&args, true, deadline);
+    }
+
+    // Matchers keep automaton states built on previous lines, so they are shared by all files.
+    let mut matchers = Vec::new();
+    if let Some(literals) = literals {
+        let automaton = AhoCorasick::new(literals, args.flags.case_insensitive);
+        matchers.push(Matcher::Literals(automaton.whole_word(args.whole_word)));
+    } else if args.fixed_strings {
+        matchers.extend(args.patterns.iter().map(|pattern| {
+            let fixed = FixedString::new(pattern, args.flags.case_insensitive);
+            Matcher::Fixed(fixed.whole_word(args.whole_word))
+        }));
+    } else {
+        matchers.extend(regexps.iter().map(|re| Matcher::Regex(re.line_matcher())));
+    }
 
-    if match_pattern(&input_line, &pattern).is_some() {
+    let style = Style {
+        mode: args.mode,
+        only_matching: args.only_matching,
+        color: match args.color {
+            ColorChoice::Never => false,
+            ColorChoice::Always => true,
+            ColorChoice::Auto => io::stdout().is_terminal(),
+        },
+        deadline,
+    };
+    let mut stdout = io::stdout().lock();
+    let mut found = false;
+
+    if args.files.is_empty() {
+        let input = Input {
+            name: "(standard input)",
+            show_name: false,
+        };
+        found |= match search(io::stdin().lock(), input, style, &mut matchers, &mut stdout) {
+            Ok(found) => found,
+            Err(e) if e.kind() == io::ErrorKind::TimedOut => exit_timed_out(e),
+            Err(e) => {
+                eprintln!("Cannot read standard input: {e}");
+                false
+            }
+        };
+    }
+
+    // Prefix lines with file name when there is more than one file to search.
+    let show_file_name = args.files.len() > 1;
+    for path in &args.files {
+        // Exit status is already known, no need to read remaining files.
+        if found && args.mode == OutputMode::Quiet {
+            break;
+        }
+
+        let input = Input {
+            name: path,
+            show_name: show_file_name,
+        };
+        let result = File::open(path).and_then(|file| {
+            search(
+                BufReader::new(file),
+                input,
+                style,
+                &mut matchers,
+                &mut stdout,
+            )
+        });
+
+        match result {
+            Ok(file_found) => found |= file_found,
+            Err(e) if e.kind() == io::ErrorKind::TimedOut => exit_timed_out(e),
+            Err(e) => eprintln!("Cannot read '{path}': {e}"),
+        }
+    }
+
+    if found {
         process::exit(0)
     } else {
         process::exit(1)
     }
 }
+
+/// Search stopped before completion, remaining input is not searched.
+fn exit_timed_out(e: io::Error) -> ! {
+    eprintln!("Search aborted: {e}");
+    process::exit(2);
+}
+
+/// Parse regexes, exiting on invalid ones. With `whole_word`, matches are surrounded by
+/// lookaround rejecting word chars.
+fn parse_regexps(args: &Args, whole_word: bool, deadline: Option<Instant>) -> Vec<Regexp> {
+    let mut regexps = Vec::with_capacity(args.patterns.len());
+
+    for original in &args.patterns {
+        let invalid = |e: GrepError| -> ! {
+            eprintln!("Invalid pattern '{original}': {e}");
+            process::exit(1);
+        };
+
+        // Other syntaxes are translated first, so they can be wrapped with extended syntax.
+        let (pattern, mut offsets) = match args.syntax {
+            Syntax::Basic => basic_to_extended_with_offsets(original),
+            Syntax::Extended => Ok((original.clone(), OffsetMap::identity(original.len()))),
+            Syntax::Perl => perl_to_extended_with_offsets(original, args.flags),
+        }
+        .unwrap_or_else(|e| invalid(e.into()));
+
+        // New line ends any verbose comment at the end of pattern.
+        let source = if whole_word {
+            let prefix = r"(?<!\w)(?:";
+            let suffix = if args.flags.verbose {
+                "\n)(?!\\w)"
+            } else {
+                r")(?!\w)"
+            };
+            offsets.wrap(prefix.len(), suffix.len());
+            format!("{prefix}{pattern}{suffix}")
+        } else {
+            pattern
+        };
+
+        // Same matches as POSIX and GNU grep, which `-o` and `--color` show, Perl ones
+        // being the first found by backtracking.
+        let match_kind = match args.syntax {
+            Syntax::Basic | Syntax::Extended => MatchKind::LeftmostLongest,
+            Syntax::Perl => MatchKind::LeftmostFirst,
+        };
+        let mut re = re_parse_translated(original, &source, &offsets, args.flags)
+            .unwrap_or_else(|e| invalid(e))
+            .with_match_kind(match_kind);
+        if let Some(limit) = args.match_limit {
+            re = re.with_match_limit(limit);
+        }
+        if let Some(deadline) = deadline {
+            re = re.with_deadline(deadline);
+        }
+        regexps.push(re);
+    }
+
+    regexps
+}
+
+/// Compiled pattern.
+enum Matcher {
+    Regex(LineMatcher),
+    Fixed(FixedString),
+    /// Many plain patterns searched at once.
+    Literals(AhoCorasick),
+}
+
+impl Matcher {
+    fn is_match(&mut self, line: &[u8]) -> Result<bool, GrepError> {
+        match self {
+            Self::Regex(matcher) => matcher.try_is_match(line),
+            Self::Fixed(fixed) => Ok(fixed.is_match(line)),
+            Self::Literals(automaton) => Ok(automaton.is_match(line)),
+        }
+    }
+
+    fn find_at(&self, line: &[u8], start: usize) -> Option<(usize, usize)> {
+        match self {
+            Self::Regex(matcher) => matcher.find_at(line, start),
+            Self::Fixed(fixed) => fixed.find_at(line, start),
+            Self::Literals(automaton) => automaton.find_at(line, start).map(|(_, span)| span),
+        }
+    }
+}
+
+/// Iterate over non empty matches of any pattern, leftmost-longest first.
+fn find_matches<'a>(
+    matchers: &'a [Matcher],
+    line: &'a [u8],
+) -> impl Iterator<Item = (usize, usize)> + 'a {
+    let mut start = 0;
+
+    iter::from_fn(move || loop {
+        if start > line.len() {
+            return None;
+        }
+
+        let (match_start, match_end) = matchers
+            .iter()
+            .filter_map(|matcher| matcher.find_at(line, start))
+            .min_by_key(|(match_start, match_end)| (*match_start, Reverse(*match_end)))?;
+
+        // Empty matches are never printed.
+        if match_start == match_end {
+            start = match_start + 1;
+            continue;
+        }
+
+        start = match_end;
+        return Some((match_start, match_end));
+    })
+}
+
+/// How matching lines are printed.
+#[derive(Clone, Copy)]
+struct Style {
+    mode: OutputMode,
+    /// Only print matched parts of lines, one per line.
+    only_matching: bool,
+    /// Highlight matches.
+    color: bool,
+    /// When to stop searching, with `--timeout`.
+    deadline: Option<Instant>,
+}
+
+/// Searched file, or standard input.
+#[derive(Clone, Copy)]
+struct Input<'a> {
+    name: &'a str,
+    /// Prefix output lines with file name.
+    show_name: bool,
+}
+
+/// Print lines matching any regex, or what `mode` asks for. Input does not need to be valid UTF-8.
+fn search(
+    reader: impl BufRead,
+    input: Input,
+    style: Style,
+    matchers: &mut [Matcher],
+    output: &mut impl Write,
+) -> io::Result<bool> {
+    let mut count = 0;
+
+    for (line_idx, line) in reader.split(b'\n').enumerate() {
+        let line = line?;
+
+        // Line terminator is not part of the line, otherwise `$` could never match before it.
+        let line = line.strip_suffix(b"\r").unwrap_or(&line);
+
+        if style
+            .deadline
+            .is_some_and(|deadline| Instant::now() >= deadline)
+        {
+            return Err(timed_out(GrepError::DeadlineExceeded));
+        }
+
+        let mut is_match = false;
+        for matcher in matchers.iter_mut() {
+            match matcher.is_match(line) {
+                Ok(matched) => is_match = matched,
+                // Lines exceeding the match limit are reported and treated as not matching.
+                Err(e @ GrepError::MatchLimitExceeded) => {
+                    eprintln!("{}:{}: {e}", input.name, line_idx + 1)
+                }
+                Err(e) => return Err(timed_out(e)),
+            }
+            if is_match {
+                break;
+            }
+        }
+        if !is_match {
+            continue;
+        }
+
+        count += 1;
+        match style.mode {
+            OutputMode::Lines => print_line(line, input, style, matchers, output)?,
+            OutputMode::Count => {}
+            // A single matching line is enough to know the answer.
+            OutputMode::FilesWithMatches | OutputMode::Quiet => break,
+        }
+    }
+
+    match style.mode {
+        OutputMode::Count if input.show_name => writeln!(output, "{}:{count}", input.name)?,
+        OutputMode::Count => writeln!(output, "{count}")?,
+        OutputMode::FilesWithMatches if count > 0 => writeln!(output, "{}", input.name)?,
+        _ => {}
+    }
+
+    Ok(count > 0)
+}
+
+fn timed_out(e: GrepError) -> io::Error {
+    io::Error::new(io::ErrorKind::TimedOut, e)
+}
+
+/// Print matching line, or only its matches, highlighting them if needed.
+fn print_line(
+    line: &[u8],
+    input: Input,
+    style: Style,
+    matchers: &[Matcher],
+    output: &mut impl Write,
+) -> io::Result<()> {
+    let (color, reset) = if style.color {
+        (MATCH_COLOR, RESET_COLOR)
+    } else {
+        ("", "")
+    };
+
+    if style.only_matching {
+        for (start, end) in find_matches(matchers, line) {
+            if input.show_name {
+                write!(output, "{}:", input.name)?;
+            }
+            write!(output, "{color}")?;
+            output.write_all(&line[start..end])?;
+            writeln!(output, "{reset}")?;
+        }
+        return Ok(());
+    }
+
+    if input.show_name {
+        write!(output, "{}:", input.name)?;
+    }
+
+    if style.color {
+        let mut printed = 0;
+        for (start, end) in find_matches(matchers, line) {
+            output.write_all(&line[printed..start])?;
+            write!(output, "{color}")?;
+            output.write_all(&line[start..end])?;
+            write!(output, "{reset}")?;
+            printed = end;
+        }
+        output.write_all(&line[printed..])?;
+    } else {
+        output.write_all(line)?;
+    }
+
+    output.write_all(b"\n")
+}
@@ -0,0 +1,848 @@
+use std::{ops::Range, slice};
+
+use crate::{
+    is_word_char, sequence_width, Assertion, Ast, AstKind, BracketItem, Class, ClassItem, Flags,
+    GroupKind, ParseError, ParseErrorKind, Pattern, UnicodeProperty,
+};
+
+/// Maximum count of a `{n,m}` repetition, as repeated patterns are copied.
+const MAX_REPETITION: usize = 1000;
+
+/// Maximum nesting of groups and repetitions, as patterns are walked recursively.
+const MAX_NESTING: usize = 128;
+
+/// Maximum number of pattern nodes copied when expanding repetitions.
+const MAX_COPIES: usize = 100_000;
+
+/// Maximum depth of expanded patterns, `{n,m}` optional copies being nested in each other.
+const MAX_DEPTH: usize = 2500;
+
+const ASCII_DIGITS: &[u8] = b"0123456789";
+
+type Span = Range<usize>;
+
+/// Parse pattern to its syntax tree, `flags` being the ones in effect at its start.
+pub(crate) fn parse(pattern: &str, flags: Flags) -> Result<Ast, ParseError> {
+    let mut parser = Parser {
+        lexer: Lexer { pattern, pos: 0 },
+        flags,
+        depth: 0,
+        capture_count: 0,
+    };
+    parser.alternation().map(|(ast, _)| ast)
+}
+
+/// Lower syntax tree to the patterns run by the matching engines.
+pub(crate) fn lower(pattern: &str, ast: &Ast) -> Result<Vec<Pattern>, ParseError> {
+    let mut lowering = Lowering {
+        pattern,
+        size: 0,
+        copies: 0,
+        depth: 0,
+    };
+    let patterns = lowering.sequence(ast)?;
+    if patterns.is_empty() {
+        let span = 0..pattern.len();
+        return Err(ParseError::new(pattern, ParseErrorKind::EmptyPattern, span));
+    }
+    Ok(patterns)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token<'a> {
+    Char(char),
+    /// `\xNN` escape.
+    Hex(u8),
+    Dot,
+    Caret,
+    Dollar,
+    /// `\A`, `\z`, `\Z` or `\K`.
+    Assertion(Assertion),
+    Digit,
+    Word,
+    Property {
+        property: UnicodeProperty,
+        negated: bool,
+    },
+    /// Bracket expression, hex escapes in it being bytes.
+    Bracket {
+        items: Vec<BracketItem>,
+        negated: bool,
+        content: Span,
+    },
+    Quantifier {
+        min: usize,
+        max: Option<usize>,
+        lazy: bool,
+    },
+    BackReference(usize),
+    /// Inline flags changes, like `i-s` for `(?i-s)`.
+    SetFlags(&'a str),
+    Open(Open<'a>),
+    Close,
+    Pipe,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Open<'a> {
+    Capture,
+    /// `(?:` or `(?i-s:` with its flags changes.
+    Scoped(&'a str),
+    LookAhead {
+        negative: bool,
+    },
+    LookBehind {
+        negative: bool,
+    },
+}
+
+/// Split pattern in tokens, skipping whitespace and comments when asked to by verbose mode.
+#[derive(Clone)]
+struct Lexer<'a> {
+    pattern: &'a str,
+    pos: usize,
+}
+
+impl<'a> Lexer<'a> {
+    fn peek(&self, verbose: bool) -> Result<Option<(Token<'a>, Span)>, ParseError> {
+        self.clone().next(verbose)
+    }
+
+    fn next(&mut self, verbose: bool) -> Result<Option<(Token<'a>, Span)>, ParseError> {
+        if verbose {
+            self.skip_ignored();
+        }
+
+        let start = self.pos;
+        let rest = &self.pattern[start..];
+        let Some(c) = rest.chars().next() else {
+            return Ok(None);
+        };
+
+        let (token, len) = match c {
+            '*' => quantifier(rest, 1, 0, None),
+            '+' => quantifier(rest, 1, 1, None),
+            '?' => quantifier(rest, 1, 0, Some(1)),
+            '{' => match self.repetition(rest)? {
+                Some((len, min, max)) => quantifier(rest, len, min, max),
+                // Braces not making a valid repetition are literal.
+                None => (Token::Char('{'), 1),
+            },
+            '^' => (Token::Caret, 1),
+            '$' => (Token::Dollar, 1),
+            '.' => (Token::Dot, 1),
+            '|' => (Token::Pipe, 1),
+            ')' => (Token::Close, 1),
+            '[' => self.bracket(rest)?,
+            '(' => self.open(rest)?,
+            '\\' => self.escape(rest, verbose)?,
+            c => (Token::Char(c), c.len_utf8()),
+        };
+
+        self.pos = start + len;
+        Ok(Some((token, start..self.pos)))
+    }
+
+    /// Skip whitespace and `#` comments.
+    fn skip_ignored(&mut self) {
+        loop {
+            let rest = &self.pattern[self.pos..];
+            let trimmed = rest.trim_start();
+            self.pos += rest.len() - trimmed.len();
+
+            if !trimmed.starts_with('#') {
+                return;
+            }
+            self.pos += trimmed.find('\n').map_or(trimmed.len(), |end| end + 1);
+        }
+    }
+
+    /// Parse `{n}`, `{n,}`, `{,m}` or `{n,m}` repetition, returning its length and counts.
+    fn repetition(&self, rest: &str) -> Result<Option<(usize, usize, Option<usize>)>, ParseError> {
+        let Some((bounds, _)) = rest[1..].split_once('}') else {
+            return Ok(None);
+        };
+
+        let is_count =
+            |digits: &str| !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit());
+        let (min, max) = match bounds.split_once(',') {
+            None if is_count(bounds) => (bounds, Some(bounds)),
+            Some((min, "")) if is_count(min) => (min, None),
+            Some(("", max)) if is_count(max) => ("0", Some(max)),
+            Some((min, max)) if is_count(min) && is_count(max) => (min, Some(max)),
+            _ => return Ok(None),
+        };
+
+        let len = bounds.len() + 2;
+        let invalid = || self.error(ParseErrorKind::InvalidRepetition, 0, len);
+        let count = |digits: &str| {
+            digits
+                .parse()
+                .ok()
+                .filter(|count| *count <= MAX_REPETITION)
+                .ok_or_else(invalid)
+        };
+        let min = count(min)?;
+        let max = max.map(count).transpose()?;
+        if max.is_some_and(|max| max < min) {
+            return Err(invalid());
+        }
+
+        Ok(Some((len, min, max)))
+    }
+
+    /// Parse group opening, inline flags being a group only when scoped like `(?i:...)`.
+    fn open(&self, rest: &'a str) -> Result<(Token<'a>, usize), ParseError> {
+        if let Some(flags) = rest.strip_prefix("(?").filter(|flags| {
+            flags.starts_with(|c: char| c.is_ascii_alphabetic() || c == '-' || c == ':')
+        }) {
+            let mut negated = false;
+            for (idx, c) in flags.char_indices() {
+                match c {
+                    'i' | 'm' | 's' | 'x' | 'u' => {}
+                    '-' if !negated => negated = true,
+                    ')' => return Ok((Token::SetFlags(&flags[..idx]), idx + 3)),
+                    ':' => return Ok((Token::Open(Open::Scoped(&flags[..idx])), idx + 3)),
+                    _ => {
+                        let span = 2 + idx..2 + idx + c.len_utf8();
+                        return Err(self.error(ParseErrorKind::UnknownFlag, span.start, span.end));
+                    }
+                }
+            }
+            return Err(self.unclosed_group());
+        }
+
+        let (open, len) = if rest.starts_with("(?<=") {
+            (Open::LookBehind { negative: false }, 4)
+        } else if rest.starts_with("(?<!") {
+            (Open::LookBehind { negative: true }, 4)
+        } else if rest.starts_with("(?=") {
+            (Open::LookAhead { negative: false }, 3)
+        } else if rest.starts_with("(?!") {
+            (Open::LookAhead { negative: true }, 3)
+        } else {
+            (Open::Capture, 1)
+        };
+        Ok((Token::Open(open), len))
+    }
+
+    /// Parse escape sequence, `rest` starting with its `\`.
+    fn escape(&self, rest: &str, verbose: bool) -> Result<(Token<'a>, usize), ParseError> {
+        let Some(c) = rest[1..].chars().next() else {
+            return Err(self.error(ParseErrorKind::TrailingBackslash, 0, 1));
+        };
+
+        let token = match c {
+            'A' => Token::Assertion(Assertion::Start),
+            'z' => Token::Assertion(Assertion::End),
+            'Z' => Token::Assertion(Assertion::EndBeforeNewline),
+            'K' => Token::Assertion(Assertion::ResetStart),
+            'd' => Token::Digit,
+            'w' => Token::Word,
+            'x' => return Ok((Token::Hex(self.hex(rest, 0)?), 4)),
+            'p' | 'P' => {
+                let (property, negated, len) = self.property(rest, 0)?;
+                return Ok((Token::Property { property, negated }, len));
+            }
+            // Only single digit back references are parsed.
+            '1'..='9' => Token::BackReference(c as usize - '0' as usize),
+            // Escaped chars that would otherwise be ignored in verbose mode.
+            c if verbose && (c.is_whitespace() || c == '#') => Token::Char(c),
+            // Escaped special chars, like `\.` or `\(`, are matched literally.
+            c if c.is_ascii_punctuation() => Token::Char(c),
+            c => {
+                let len = 1 + c.len_utf8();
+                return Err(self.error(ParseErrorKind::InvalidEscape, 0, len));
+            }
+        };
+        Ok((token, 1 + c.len_utf8()))
+    }
+
+    /// Parse `\xNN` escape made of exactly two hex digits, at `offset` from current position.
+    fn hex(&self, input: &str, offset: usize) -> Result<u8, ParseError> {
+        input
+            .get(2..4)
+            .filter(|digits| digits.bytes().all(|b| b.is_ascii_hexdigit()))
+            .and_then(|digits| u8::from_str_radix(digits, 16).ok())
+            .ok_or_else(|| self.error(ParseErrorKind::InvalidEscape, offset, offset + 2))
+    }
+
+    /// Parse `\p{...}`, `\P{...}` or single letter `\pL` property, at `offset` from current
+    /// position, returning it with whether it is negated and its length.
+    fn property(
+        &self,
+        input: &str,
+        offset: usize,
+    ) -> Result<(UnicodeProperty, bool, usize), ParseError> {
+        let negated = input.starts_with(r"\P");
+        let rest = &input[2..];
+        let invalid = || self.error(ParseErrorKind::InvalidEscape, offset, offset + 2);
+
+        let (name, len) = if let Some(rest) = rest.strip_prefix('{') {
+            let end = rest.find('}').ok_or_else(invalid)?;
+            (&rest[..end], end + 4)
+        } else {
+            let c = rest.chars().next().ok_or_else(invalid)?;
+            (&rest[..c.len_utf8()], c.len_utf8() + 2)
+        };
+
+        let property = UnicodeProperty::from_name(name).ok_or_else(|| {
+            let kind = ParseErrorKind::UnknownProperty(name.to_string());
+            self.error(kind, offset, offset + len)
+        })?;
+        Ok((property, negated, len))
+    }
+
+    /// Parse bracket expression, ended by the first `]`.
+    fn bracket(&self, rest: &str) -> Result<(Token<'a>, usize), ParseError> {
+        let Some(end) = rest[1..].find(']') else {
+            return Err(self.error(ParseErrorKind::UnclosedBracket, 0, 1));
+        };
+        let content = &rest[1..1 + end];
+
+        // POSIX classes like `[:alpha:]` are not supported, other `[` are literal.
+        if let Some((idx, _)) = content
+            .match_indices('[')
+            .find(|(idx, _)| content[idx + 1..].starts_with([':', '=', '.']))
+        {
+            let part = &rest[1 + idx..end + 2];
+            let kind = ParseErrorKind::UnsupportedSyntax(part.to_string());
+            return Err(self.error(kind, 1 + idx, end + 2));
+        }
+
+        let (negated, mut offset) = match content.starts_with('^') {
+            true => (true, 2),
+            false => (false, 1),
+        };
+        let mut items = Vec::new();
+        while let Some(c) = rest[offset..1 + end].chars().next() {
+            let input = &rest[offset..1 + end];
+            if input.starts_with(r"\p") || input.starts_with(r"\P") {
+                let (property, negated, len) = self.property(input, offset)?;
+                items.push(BracketItem::Property { property, negated });
+                offset += len;
+            } else if input.starts_with(r"\x") {
+                items.push(BracketItem::Byte(self.hex(input, offset)?));
+                offset += 4;
+            } else {
+                items.push(BracketItem::Char(c));
+                offset += c.len_utf8();
+            }
+        }
+
+        let content = self.pos + 1..self.pos + 1 + end;
+        let token = Token::Bracket {
+            items,
+            negated,
+            content,
+        };
+        Ok((token, end + 2))
+    }
+
+    fn unclosed_group(&self) -> ParseError {
+        let end = self.pattern.len();
+        ParseError::new(self.pattern, ParseErrorKind::UnclosedGroup, end..end)
+    }
+
+    /// Error about `start..end` range relative to current position.
+    fn error(&self, kind: ParseErrorKind, start: usize, end: usize) -> ParseError {
+        ParseError::new(self.pattern, kind, self.pos + start..self.pos + end)
+    }
+}
+
+/// Quantifier token of `len` bytes, lazy when followed by `?`.
+fn quantifier<'a>(rest: &str, len: usize, min: usize, max: Option<usize>) -> (Token<'a>, usize) {
+    let lazy = rest[len..].starts_with('?');
+    (
+        Token::Quantifier { min, max, lazy },
+        len + usize::from(lazy),
+    )
+}
+
+/// Recursive descent parser, a group being an alternation of concatenations.
+struct Parser<'a> {
+    lexer: Lexer<'a>,
+    /// Flags in effect at current position.
+    flags: Flags,
+    /// Number of groups being parsed, `)` only closing a group when in one.
+    depth: usize,
+    capture_count: usize,
+}
+
+/// Nesting of groups and repetitions in a node, along its deepest path.
+type Nesting = usize;
+
+impl Parser<'_> {
+    fn alternation(&mut self) -> Result<(Ast, Nesting), ParseError> {
+        let flags = self.flags;
+        let (first, mut nesting) = self.concat()?;
+        let mut alternatives = vec![first];
+        while let Some((Token::Pipe, _)) = self.lexer.peek(self.flags.verbose)? {
+            self.lexer.next(self.flags.verbose)?;
+            let (alternative, alternative_nesting) = self.concat()?;
+            alternatives.push(alternative);
+            nesting = nesting.max(alternative_nesting);
+        }
+
+        if alternatives.len() == 1 {
+            return Ok((alternatives.remove(0), nesting));
+        }
+        let start = alternatives[0].span.start;
+        let end = alternatives[alternatives.len() - 1].span.end;
+        let ast = Ast {
+            kind: AstKind::Alternation(alternatives),
+            span: start..end,
+            flags,
+        };
+        Ok((ast, nesting))
+    }
+
+    fn concat(&mut self) -> Result<(Ast, Nesting), ParseError> {
+        let flags = self.flags;
+        let start = self.lexer.pos;
+        let mut items: Vec<Ast> = Vec::new();
+        // Nesting of each item, quantifiers applying to the last one.
+        let mut nestings: Vec<Nesting> = Vec::new();
+
+        loop {
+            let checkpoint = self.lexer.clone();
+            let Some((token, span)) = self.lexer.next(self.flags.verbose)? else {
+                break;
+            };
+
+            let kind = match token {
+                Token::Pipe => {
+                    self.lexer = checkpoint;
+                    break;
+                }
+                Token::Close if self.depth > 0 => {
+                    self.lexer = checkpoint;
+                    break;
+                }
+                // `)` outside of groups is literal, like in GNU grep.
+                Token::Close => AstKind::Literal(')'),
+                Token::Quantifier { min, max, lazy } => {
+                    let Some(ast) = items.pop().filter(|ast| ast.kind != AstKind::Flags) else {
+                        return Err(self.error(ParseErrorKind::NothingToRepeat, span));
+                    };
+                    let nesting = nestings.pop().unwrap_or_default() + 1;
+                    if nesting > MAX_NESTING {
+                        return Err(self.error(ParseErrorKind::NestingTooDeep, span));
+                    }
+
+                    let span = ast.span.start..span.end;
+                    let kind = AstKind::Repetition {
+                        ast: Box::new(ast),
+                        min,
+                        max,
+                        lazy,
+                    };
+                    items.push(self.node(kind, span));
+                    nestings.push(nesting);
+                    continue;
+                }
+                Token::SetFlags(changes) => {
+                    self.flags = self.flags.apply(changes);
+                    AstKind::Flags
+                }
+                Token::Open(open) => {
+                    let (group, nesting) = self.group(open, span)?;
+                    items.push(group);
+                    nestings.push(nesting);
+                    continue;
+                }
+                Token::Char(c) => AstKind::Literal(c),
+                Token::Hex(byte) if self.flags.bytes && !byte.is_ascii() => AstKind::Byte(byte),
+                Token::Hex(byte) => AstKind::Literal(char::from(byte)),
+                Token::Dot => AstKind::Dot,
+                Token::Caret if self.flags.multi_line => AstKind::Assertion(Assertion::LineStart),
+                Token::Caret => AstKind::Assertion(Assertion::Start),
+                Token::Dollar if self.flags.multi_line => AstKind::Assertion(Assertion::LineEnd),
+                Token::Dollar => AstKind::Assertion(Assertion::End),
+                Token::Assertion(assertion) => AstKind::Assertion(assertion),
+                Token::Digit => AstKind::Class(Class::Digit),
+                Token::Word => AstKind::Class(Class::Word),
+                Token::Property { .. } if self.flags.bytes => {
+                    return Err(self.error(ParseErrorKind::UnicodeInByteMode, span));
+                }
+                Token::Property { property, negated } => {
+                    AstKind::Class(Class::Property { property, negated })
+                }
+                Token::Bracket {
+                    items: bracket_items,
+                    negated,
+                    content,
+                } => AstKind::Class(Class::Bracket {
+                    items: self.bracket_items(bracket_items, content)?,
+                    negated,
+                }),
+                Token::BackReference(number) => AstKind::BackReference(number),
+            };
+            items.push(self.node(kind, span));
+            nestings.push(0);
+        }
+
+        let end = items.last().map_or(start, |ast| ast.span.end);
+        let ast = Ast {
+            kind: AstKind::Concat(items),
+            span: start..end,
+            flags,
+        };
+        Ok((ast, nestings.into_iter().max().unwrap_or_default()))
+    }
+
+    fn group(&mut self, open: Open, open_span: Span) -> Result<(Ast, Nesting), ParseError> {
+        if self.depth == MAX_NESTING {
+            return Err(self.error(ParseErrorKind::NestingTooDeep, open_span));
+        }
+
+        let outer_flags = self.flags;
+        let kind = match open {
+            Open::Capture => {
+                self.capture_count += 1;
+                GroupKind::Capture(self.capture_count)
+            }
+            Open::Scoped(changes) => {
+                self.flags = self.flags.apply(changes);
+                GroupKind::NonCapture
+            }
+            Open::LookAhead { negative } => GroupKind::LookAhead { negative },
+            Open::LookBehind { negative } => GroupKind::LookBehind { negative },
+        };
+
+        self.depth += 1;
+        let (ast, nesting) = self.alternation()?;
+        self.depth -= 1;
+
+        // Inline flags set in the group do not apply after it.
+        let close = self.lexer.next(self.flags.verbose)?;
+        self.flags = outer_flags;
+        let Some((Token::Close, close_span)) = close else {
+            return Err(self.lexer.unclosed_group());
+        };
+
+        let nesting = nesting + 1;
+        if nesting > MAX_NESTING {
+            return Err(self.error(ParseErrorKind::NestingTooDeep, open_span));
+        }
+
+        let kind = AstKind::Group {
+            kind,
+            ast: Box::new(ast),
+        };
+        Ok((self.node(kind, open_span.start..close_span.end), nesting))
+    }
+
+    /// Resolve bracket hex escapes to chars unless they are non-ASCII bytes in byte mode.
+    fn bracket_items(
+        &self,
+        items: Vec<BracketItem>,
+        content: Span,
+    ) -> Result<Vec<BracketItem>, ParseError> {
+        let mut resolved = Vec::with_capacity(items.len());
+        for item in items {
+            let item = match item {
+                BracketItem::Byte(byte) if !self.flags.bytes || byte.is_ascii() => {
+                    BracketItem::Char(char::from(byte))
+                }
+                // Byte classes only accept ASCII chars, others would match several bytes.
+                BracketItem::Char(c) if self.flags.bytes && !c.is_ascii() => {
+                    return Err(self.error(ParseErrorKind::UnicodeInByteMode, content));
+                }
+                BracketItem::Property { .. } if self.flags.bytes => {
+                    return Err(self.error(ParseErrorKind::UnicodeInByteMode, content));
+                }
+                item => item,
+            };
+            resolved.push(item);
+        }
+        Ok(resolved)
+    }
+
+    fn node(&self, kind: AstKind, span: Span) -> Ast {
+        Ast {
+            kind,
+            span,
+            flags: self.flags,
+        }
+    }
+
+    fn error(&self, kind: ParseErrorKind, span: Span) -> ParseError {
+        ParseError::new(self.lexer.pattern, kind, span)
+    }
+}
+
+impl Flags {
+    /// Apply inline flags changes like `i-s`, flags after `-` being disabled.
+    fn apply(mut self, changes: &str) -> Self {
+        let mut enable = true;
+        for c in changes.chars() {
+            match c {
+                'i' => self.case_insensitive = enable,
+                'm' => self.multi_line = enable,
+                's' => self.dot_all = enable,
+                'x' => self.verbose = enable,
+                'u' => self.bytes = !enable,
+                _ => enable = false,
+            }
+        }
+        self
+    }
+}
+
+/// Lowering of syntax trees to patterns, checking what cannot be matched.
+struct Lowering<'a> {
+    pattern: &'a str,
+    /// Number of patterns built so far, copies included.
+    size: usize,
+    /// Number of patterns copied by repetitions so far.
+    copies: usize,
+    /// Depth of the patterns being built in the expanded tree.
+    depth: usize,
+}
+
+impl Lowering<'_> {
+    /// Patterns matching node in sequence, alternations at top level being a group.
+    fn sequence(&mut self, ast: &Ast) -> Result<Vec<Pattern>, ParseError> {
+        match &ast.kind {
+            AstKind::Concat(items) => {
+                let mut patterns = Vec::with_capacity(items.len());
+                for item in items {
+                    patterns.extend(self.sequence(item)?);
+                }
+                Ok(patterns)
+            }
+            AstKind::Flags => Ok(Vec::new()),
+            _ => Ok(vec![self.pattern(ast)?]),
+        }
+    }
+
+    /// Alternatives of a group content, none of them being empty.
+    fn alternations(&mut self, ast: &Ast) -> Result<Vec<Vec<Pattern>>, ParseError> {
+        let alternatives = match &ast.kind {
+            AstKind::Alternation(alternatives) => alternatives.as_slice(),
+            _ => slice::from_ref(ast),
+        };
+
+        alternatives
+            .iter()
+            .map(|alternative| {
+                let patterns = self.sequence(alternative)?;
+                if patterns.is_empty() {
+                    let span = alternative.span.clone();
+                    return Err(self.error(ParseErrorKind::EmptyGroup, span));
+                }
+                Ok(patterns)
+            })
+            .collect()
+    }
+
+    fn pattern(&mut self, ast: &Ast) -> Result<Pattern, ParseError> {
+        self.size += 1;
+        let flags = ast.flags;
+        let pattern = match &ast.kind {
+            AstKind::Literal(c) => flags.literal(*c),
+            AstKind::Byte(byte) => Pattern::Byte(*byte),
+            AstKind::Dot => match (flags.dot_all, flags.bytes) {
+                (true, false) => Pattern::AnyChar,
+                (false, false) => Pattern::Wildcard,
+                (true, true) => flags.byte_class(Vec::new(), true),
+                (false, true) => flags.byte_class(b"\n\r".to_vec(), true),
+            },
+            AstKind::Class(Class::Digit) if flags.bytes => {
+                flags.byte_class(ASCII_DIGITS.to_vec(), false)
+            }
+            AstKind::Class(Class::Digit) => Pattern::Digit,
+            AstKind::Class(Class::Word) if flags.bytes => flags.byte_class(
+                (0..=127).filter(|b| is_word_char(char::from(*b))).collect(),
+                false,
+            ),
+            AstKind::Class(Class::Word) => Pattern::Chars,
+            AstKind::Class(Class::Property { property, negated }) => Pattern::Property {
+                property: flags.property(*property),
+                negated: *negated,
+            },
+            AstKind::Class(Class::Bracket { items, negated }) => bracket(flags, items, *negated),
+            AstKind::Assertion(assertion) => match assertion {
+                Assertion::Start => Pattern::Start,
+                Assertion::End => Pattern::End,
+                Assertion::EndBeforeNewline => Pattern::EndBeforeNewline,
+                Assertion::LineStart => Pattern::LineStart,
+                Assertion::LineEnd => Pattern::LineEnd,
+                Assertion::ResetStart => Pattern::ResetStart,
+            },
+            AstKind::BackReference(number) => Pattern::BackReference(*number),
+            AstKind::Repetition {
+                ast: repeated,
+                min,
+                max,
+                lazy,
+            } => {
+                // Optional copies are nested, each one adding a group and a quantifier.
+                let nesting = max.map_or(2, |max| 2 * (max - min) + 2);
+                self.depth += nesting;
+                if self.depth > MAX_DEPTH {
+                    return Err(self.error(ParseErrorKind::RepetitionTooLarge, ast.span.clone()));
+                }
+                let size = self.size;
+                let pattern = self.pattern(repeated)?;
+                self.depth -= nesting;
+
+                // Repeating an assertion, which does not consume any char, is meaningless.
+                if is_zero_width(repeated) {
+                    let span = repeated.span.end..ast.span.end;
+                    return Err(self.error(ParseErrorKind::NothingToRepeat, span));
+                }
+
+                // Check copies before making them, as nested repetitions multiply.
+                let count = max.unwrap_or(*min).max(1);
+                let copies = (self.size - size).saturating_mul(count - 1);
+                self.size = self.size.saturating_add(copies).saturating_add(nesting);
+                self.copies = self.copies.saturating_add(copies);
+                if self.copies > MAX_COPIES {
+                    return Err(self.error(ParseErrorKind::RepetitionTooLarge, ast.span.clone()));
+                }
+                repeat(pattern, *min, *max, *lazy)
+            }
+            AstKind::Group { kind, ast: content } => {
+                self.depth += 1;
+                let alternations = self.alternations(content)?;
+                self.depth -= 1;
+                match kind {
+                    GroupKind::Capture(id) => Pattern::Alternation {
+                        alternations,
+                        id: *id,
+                    },
+                    GroupKind::NonCapture => Pattern::Group { alternations },
+                    GroupKind::LookAhead { negative } => Pattern::LookAhead {
+                        alternations,
+                        negative: *negative,
+                    },
+                    GroupKind::LookBehind { negative } => {
+                        // Lookbehind is matched backward from the cursor, so its length must
+                        // be known.
+                        if alternations.iter().any(|alt| sequence_width(alt).is_none()) {
+                            let span = ast.span.clone();
+                            return Err(self.error(ParseErrorKind::UnboundedLookBehind, span));
+                        }
+                        Pattern::LookBehind {
+                            alternations,
+                            negative: *negative,
+                        }
+                    }
+                }
+            }
+            AstKind::Concat(_) | AstKind::Alternation(_) | AstKind::Flags => Pattern::Group {
+                alternations: self.alternations(ast)?,
+            },
+        };
+        Ok(pattern)
+    }
+
+    fn error(&self, kind: ParseErrorKind, span: Span) -> ParseError {
+        ParseError::new(self.pattern, kind, span)
+    }
+}
+
+/// Check if node never consumes any char, a repetition being checked when lowered.
+///
+/// Nested repetitions are not walked, so checking stacked quantifiers stays linear.
+fn is_zero_width(ast: &Ast) -> bool {
+    match &ast.kind {
+        AstKind::Assertion(_) | AstKind::Flags => true,
+        AstKind::Repetition { max, .. } => *max == Some(0),
+        AstKind::Group {
+            kind: GroupKind::LookAhead { .. } | GroupKind::LookBehind { .. },
+            ..
+        } => true,
+        AstKind::Group { ast, .. } => is_zero_width(ast),
+        AstKind::Concat(items) | AstKind::Alternation(items) => items.iter().all(is_zero_width),
+        AstKind::Literal(_) | AstKind::Byte(_) | AstKind::Dot | AstKind::Class(_) => false,
+        AstKind::BackReference(_) => false,
+    }
+}
+
+/// Pattern matching a bracket expression, with the case variants of its chars.
+fn bracket(flags: Flags, bracket_items: &[BracketItem], negated: bool) -> Pattern {
+    let mut items = Vec::new();
+    let mut chars = Vec::new();
+    let mut bytes = Vec::new();
+
+    for item in bracket_items {
+        match item {
+            BracketItem::Char(c) => {
+                for variant in flags.case_variants(*c) {
+                    // Case variant of an ASCII char may not be ASCII, like Kelvin sign for `k`.
+                    if flags.bytes && !variant.is_ascii() {
+                        continue;
+                    }
+                    if !chars.contains(&variant) {
+                        chars.push(variant);
+                        items.push(ClassItem::Char(variant));
+                    }
+                }
+            }
+            BracketItem::Byte(byte) => bytes.push(*byte),
+            BracketItem::Property { property, negated } => items.push(ClassItem::Property {
+                property: flags.property(*property),
+                negated: *negated,
+            }),
+        }
+    }
+
+    if flags.bytes {
+        bytes.extend(chars.iter().map(|c| *c as u8));
+        return Pattern::ByteClass { bytes, negated };
+    }
+
+    // Keep simple char groups when no unicode property is involved.
+    match (items.len() == chars.len(), negated) {
+        (true, false) => Pattern::PositiveCharGroup(chars),
+        (true, true) => Pattern::NegativeCharGroup(chars),
+        (false, negated) => Pattern::CharClass { items, negated },
+    }
+}
+
+/// Expand `{min,max}` repetition of pattern with copies of it, as a single pattern, an
+/// empty group when repeated zero times.
+fn repeat(pattern: Pattern, min: usize, max: Option<usize>, lazy: bool) -> Pattern {
+    type Quantifier = fn(Box<Pattern>) -> Pattern;
+    let (one_or_more, zero_or_one): (Quantifier, Quantifier) = if lazy {
+        (Pattern::LazyOneOrMore, Pattern::LazyZeroOrOne)
+    } else {
+        (Pattern::OneOrMore, Pattern::ZeroOrOne)
+    };
+
+    let mut sequence = vec![pattern.clone(); min];
+    match max {
+        None if min == 0 => {
+            let repeated = one_or_more(Box::new(pattern));
+            return zero_or_one(Box::new(repeated));
+        }
+        None => {
+            sequence.pop();
+            sequence.push(one_or_more(Box::new(pattern)));
+        }
+        Some(max) => {
+            // Optional copies are nested, so a copy is only tried after the previous one.
+            let mut optional = None;
+            for _ in min..max {
+                let inner = match optional {
+                    None => pattern.clone(),
+                    Some(optional) => Pattern::Group {
+                        alternations: vec![vec![pattern.clone(), optional]],
+                    },
+                };
+                optional = Some(zero_or_one(Box::new(inner)));
+            }
+            sequence.extend(optional);
+        }
+    }
+
+    match <[Pattern; 1]>::try_from(sequence) {
+        Ok([pattern]) => pattern,
+        Err(sequence) => Pattern::Group {
+            alternations: vec![sequence],
+        },
+    }
+}
@@ -0,0 +1,560 @@
+use std::{collections::HashMap, iter::Peekable, str::CharIndices};
+
+use crate::{Flags, OffsetMap, ParseError, ParseErrorKind};
+
+/// Horizontal whitespace matched by `\h`.
+const HORIZONTAL_SPACES: &str = "\t \u{a0}\u{1680}\u{180e}\u{2000}\u{2001}\u{2002}\u{2003}\
+    \u{2004}\u{2005}\u{2006}\u{2007}\u{2008}\u{2009}\u{200a}\u{202f}\u{205f}\u{3000}";
+/// Vertical whitespace matched by `\v`.
+const VERTICAL_SPACES: &str = "\n\u{b}\u{c}\r\u{85}\u{2028}\u{2029}";
+/// Word chars matched by `\w`, as a bracket expression content.
+const WORD_CHARS: &str = r"\p{L}\p{N}_";
+/// Largest `a-z` range in a bracket expression, whose chars are all listed.
+const MAX_RANGE_LEN: u32 = 256;
+
+/// Translate a Perl-compatible regular expression (PCRE), as used by `grep -P`, to the syntax
+/// parsed by [`re_parse`](crate::re_parse).
+///
+/// Named groups are numbered and references to them replaced, while escapes like `\h`, `\R`,
+/// `\s` or `\b` and `\Q...\E` quoting are expanded. Like in PCRE, `.` only excludes `\n` and
+/// verbose comments are dropped. Constructs having another meaning here or
+/// not supported by the engine, like possessive quantifiers or atomic groups, are rejected
+/// with [`ParseErrorKind::UnsupportedSyntax`].
+pub fn perl_to_extended(pattern: &str) -> Result<String, ParseError> {
+    perl_to_extended_with_offsets(pattern, Flags::default()).map(|(output, _)| output)
+}
+
+/// Translate Perl-compatible regular expression like [`perl_to_extended`], with the offsets
+/// of the translation in the pattern, `flags` being in effect at its start.
+pub fn perl_to_extended_with_offsets(
+    pattern: &str,
+    flags: Flags,
+) -> Result<(String, OffsetMap), ParseError> {
+    let mut translator = Translator {
+        pattern,
+        chars: pattern.char_indices().peekable(),
+        output: String::with_capacity(pattern.len()),
+        flags,
+        outer_flags: Vec::new(),
+        group_count: 0,
+        names: HashMap::new(),
+        offsets: OffsetMap::default(),
+    };
+    translator.translate()?;
+    translator
+        .offsets
+        .finish(translator.output.len(), pattern.len());
+    Ok((translator.output, translator.offsets))
+}
+
+struct Translator<'a> {
+    pattern: &'a str,
+    chars: Peekable<CharIndices<'a>>,
+    output: String,
+    /// Flags in effect, `x` and `s` changing the translation.
+    flags: Flags,
+    /// Flags of enclosing groups, restored when they are closed.
+    outer_flags: Vec<Flags>,
+    group_count: usize,
+    names: HashMap<&'a str, usize>,
+    offsets: OffsetMap,
+}
+
+impl<'a> Translator<'a> {
+    fn translate(&mut self) -> Result<(), ParseError> {
+        while let Some((idx, c)) = self.chars.next() {
+            self.offsets.push(self.output.len(), idx);
+            match c {
+                '\\' => self.escape(idx)?,
+                '[' => self.bracket(idx)?,
+                '(' => self.group(idx)?,
+                ')' => {
+                    self.flags = self.outer_flags.pop().ok_or_else(|| {
+                        self.error(ParseErrorKind::UnmatchedParenthesis, idx, idx + 1)
+                    })?;
+                    self.output.push(c);
+                }
+                '*' | '+' | '?' => {
+                    self.output.push(c);
+                    self.quantifier_suffix(idx)?;
+                }
+                '{' => match self.repetition_len(idx) {
+                    Some(len) => {
+                        self.output.push_str(&self.pattern[idx..idx + len]);
+                        self.skip_to(idx + len);
+                        self.quantifier_suffix(idx)?;
+                    }
+                    // Braces not making a repetition, like `{,3}`, are literal.
+                    None => push_literal(&mut self.output, c),
+                },
+                // Comments go up to the end of line.
+                '#' if self.flags.verbose => {
+                    while self.chars.next_if(|(_, c)| *c != '\n').is_some() {}
+                }
+                '.' if !self.flags.dot_all => self.output.push_str(r"[^\x0A]"),
+                _ => self.output.push(c),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Copy `?` making a quantifier lazy, rejecting possessive `+`.
+    fn quantifier_suffix(&mut self, start: usize) -> Result<(), ParseError> {
+        if let Some((idx, _)) = self.chars.next_if(|(_, c)| *c == '+') {
+            return Err(self.unsupported(start, idx + 1));
+        }
+        if self.chars.next_if(|(_, c)| *c == '?').is_some() {
+            self.output.push('?');
+        }
+        Ok(())
+    }
+
+    /// Length of `{n}`, `{n,}` or `{n,m}` repetition starting at `start`.
+    fn repetition_len(&self, start: usize) -> Option<usize> {
+        let (bounds, _) = self.pattern[start + 1..].split_once('}')?;
+        let (min, max) = bounds.split_once(',').unwrap_or((bounds, ""));
+        let is_count = |digits: &str| digits.bytes().all(|b| b.is_ascii_digit());
+        (!min.is_empty() && is_count(min) && is_count(max)).then_some(bounds.len() + 2)
+    }
+
+    /// Translate escape sequence, `start` being the index of its `\`.
+    fn escape(&mut self, start: usize) -> Result<(), ParseError> {
+        let (idx, c) = self
+            .chars
+            .next()
+            .ok_or_else(|| self.error(ParseErrorKind::TrailingBackslash, start, start + 1))?;
+        match c {
+            'Q' => {
+                // Everything up to `\E` is literal.
+                let rem = &self.pattern[idx + 1..];
+                let (quoted, len) = match rem.find(r"\E") {
+                    Some(end) => (&rem[..end], end + 2),
+                    None => (rem, rem.len()),
+                };
+                quoted
+                    .chars()
+                    .for_each(|c| push_literal(&mut self.output, c));
+                self.skip_to(idx + len + 1);
+            }
+            // End of quoting without start is ignored.
+            'E' => {}
+            '1'..='9' => {
+                let digits = self.take_while(idx, |c| c.is_ascii_digit());
+                let number = digits.parse().map_err(|_| {
+                    let end = idx + digits.len();
+                    self.error(ParseErrorKind::InvalidGroupReference, start, end)
+                })?;
+                self.back_reference(start, number)?;
+            }
+            'k' => {
+                let name = self.take_delimited(start)?;
+                let number = self.named_group(name, start)?;
+                self.back_reference(start, number)?;
+            }
+            'g' => {
+                let reference = match self.chars.peek() {
+                    Some((_, '{')) => self.take_delimited(start)?,
+                    Some((idx, '-' | '0'..='9')) => {
+                        let idx = *idx;
+                        self.chars.next();
+                        let digits = self.take_while(idx + 1, |c| c.is_ascii_digit());
+                        &self.pattern[idx..idx + 1 + digits.len()]
+                    }
+                    // Subroutine calls like `\g<name>`.
+                    next => {
+                        let end = next.map_or(idx + 1, |(next, c)| next + c.len_utf8());
+                        return Err(self.unsupported(start, end));
+                    }
+                };
+                let number = self.group_reference(reference, start)?;
+                self.back_reference(start, number)?;
+            }
+            'h' => self.output.push_str(&format!("[{HORIZONTAL_SPACES}]")),
+            'H' => self.output.push_str(&format!("[^{HORIZONTAL_SPACES}]")),
+            'v' => self.output.push_str(&format!("[{VERTICAL_SPACES}]")),
+            'V' => self.output.push_str(&format!("[^{VERTICAL_SPACES}]")),
+            // Any line break, `\r\n` being matched as a single one.
+            'R' => self
+                .output
+                .push_str(&format!(r"(?:\x0D\x0A|[{VERTICAL_SPACES}])")),
+            's' => self.output.push_str(r"\p{White_Space}"),
+            'S' => self.output.push_str(r"\P{White_Space}"),
+            'w' => self.output.push_str(&format!("[{WORD_CHARS}]")),
+            'W' => self.output.push_str(&format!("[^{WORD_CHARS}]")),
+            'D' => self.output.push_str("[^0123456789]"),
+            // Word boundaries, with a word char on one side only or on both sides.
+            'b' => self.output.push_str(&format!(
+                "(?:(?<=[{WORD_CHARS}])(?![{WORD_CHARS}])|(?<![{WORD_CHARS}])(?=[{WORD_CHARS}]))"
+            )),
+            'B' => self.output.push_str(&format!(
+                "(?:(?<=[{WORD_CHARS}])(?=[{WORD_CHARS}])|(?<![{WORD_CHARS}])(?![{WORD_CHARS}]))"
+            )),
+            'd' | 'A' | 'z' | 'Z' | 'K' => {
+                self.output.push('\\');
+                self.output.push(c);
+            }
+            'p' | 'P' => {
+                let len = self.property_len(idx + 1)?;
+                self.output.push_str(&self.pattern[start..idx + 1 + len]);
+                self.skip_to(idx + len + 1);
+            }
+            'x' => {
+                let c = self.hex_escape(start)?;
+                push_literal(&mut self.output, c);
+            }
+            c => {
+                let c = self.escaped_char(start, c)?;
+                push_literal(&mut self.output, c);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Char matched by escape sequence standing for a single char, like `\t` or `\.`.
+    fn escaped_char(&mut self, start: usize, c: char) -> Result<char, ParseError> {
+        match c {
+            't' => Ok('\t'),
+            'n' => Ok('\n'),
+            'r' => Ok('\r'),
+            'f' => Ok('\u{c}'),
+            'e' => Ok('\u{1b}'),
+            'a' => Ok('\u{7}'),
+            'x' => self.hex_escape(start),
+            c if !c.is_ascii_alphanumeric() => Ok(c),
+            // Octal escapes, `\G`, `\X` or case conversion have no equivalent.
+            c => Err(self.unsupported(start, start + 1 + c.len_utf8())),
+        }
+    }
+
+    /// Parse `\xNN` or `\x{N...}` escape, `start` being the index of its `\`.
+    fn hex_escape(&mut self, start: usize) -> Result<char, ParseError> {
+        let digits = match self.chars.peek() {
+            Some((_, '{')) => self.take_delimited(start)?,
+            Some((idx, _)) => {
+                let idx = *idx;
+                let len = self.pattern[idx..]
+                    .bytes()
+                    .take(2)
+                    .take_while(u8::is_ascii_hexdigit)
+                    .count();
+                self.skip_to(idx + len);
+                &self.pattern[idx..idx + len]
+            }
+            None => "",
+        };
+
+        u32::from_str_radix(digits, 16)
+            .ok()
+            .and_then(char::from_u32)
+            .ok_or_else(|| self.error(ParseErrorKind::InvalidEscape, start, start + 2))
+    }
+
+    /// Length of property name after `\p`, either `{...}` or a single letter.
+    fn property_len(&self, start: usize) -> Result<usize, ParseError> {
+        let rem = &self.pattern[start..];
+        if rem.starts_with('{') {
+            rem.find('}').map(|end| end + 1)
+        } else {
+            rem.chars().next().map(char::len_utf8)
+        }
+        .ok_or_else(|| self.error(ParseErrorKind::InvalidEscape, start - 2, start))
+    }
+
+    /// Translate group opening, `start` being the index of its `(`.
+    fn group(&mut self, start: usize) -> Result<(), ParseError> {
+        let rem = &self.pattern[start + 1..];
+
+        if rem.starts_with('*') {
+            // Backtracking control verbs like `(*SKIP)`.
+            let end = rem
+                .find(')')
+                .map_or(self.pattern.len(), |end| start + end + 2);
+            return Err(self.unsupported(start, end));
+        }
+        let Some(rem) = rem.strip_prefix('?') else {
+            self.group_count += 1;
+            self.outer_flags.push(self.flags);
+            self.output.push('(');
+            return Ok(());
+        };
+        self.chars.next();
+
+        if rem.starts_with('#') {
+            // Comments are dropped.
+            let end = rem
+                .find(')')
+                .ok_or_else(|| self.error(ParseErrorKind::UnclosedGroup, start, start + 1))?;
+            self.skip_to(start + 2 + end + 1);
+        } else if let Some(rem) = rem.strip_prefix("P=") {
+            let end = rem
+                .find(')')
+                .ok_or_else(|| self.error(ParseErrorKind::UnclosedGroup, start, start + 1))?;
+            let name = &rem[..end];
+            self.skip_to(start + 4 + end + 1);
+            let number = self.named_group(name, start)?;
+            self.back_reference(start, number)?;
+        } else if let Some((name, close)) = [("P<", '>'), ("<", '>'), ("'", '\'')]
+            .into_iter()
+            .find_map(|(open, close)| Some((rem.strip_prefix(open)?, close)))
+            .filter(|(rem, _)| rem.starts_with(|c: char| c.is_alphabetic() || c == '_'))
+        {
+            let name_start = self.pattern.len() - name.len();
+            let name_len = name
+                .find(|c: char| !c.is_alphanumeric() && c != '_')
+                .filter(|len| name[*len..].starts_with(close))
+                .ok_or_else(|| {
+                    let end = self.pattern.len();
+                    self.error(ParseErrorKind::InvalidGroupName, name_start, end)
+                })?;
+            let name = &name[..name_len];
+            self.skip_to(name_start + name_len + 1);
+
+            self.group_count += 1;
+            if self.names.insert(name, self.group_count).is_some() {
+                let kind = ParseErrorKind::DuplicateGroupName(name.to_string());
+                return Err(self.error(kind, name_start, name_start + name_len));
+            }
+            self.outer_flags.push(self.flags);
+            self.output.push('(');
+        } else if ["=", "!", "<=", "<!", ":"]
+            .into_iter()
+            .any(|prefix| rem.starts_with(prefix))
+        {
+            self.outer_flags.push(self.flags);
+            self.output.push_str("(?");
+        } else {
+            // Inline flags, either scoped to a group or applying to the rest of it.
+            let len = rem
+                .find(|c: char| !matches!(c, 'i' | 'm' | 's' | 'x' | '-'))
+                .unwrap_or(rem.len());
+            match rem[len..].chars().next() {
+                Some(':') => self.outer_flags.push(self.flags),
+                Some(')') => {}
+                // Atomic groups, branch reset, conditionals or recursion.
+                c => {
+                    let end = start + 2 + len + c.map_or(0, char::len_utf8);
+                    return Err(self.unsupported(start, end));
+                }
+            }
+            self.set_flags(&rem[..len]);
+            self.output.push_str("(?");
+            self.output.push_str(&rem[..=len]);
+            self.skip_to(start + 2 + len + 1);
+        }
+
+        Ok(())
+    }
+
+    /// Apply inline flags like `x-s`.
+    fn set_flags(&mut self, flags: &str) {
+        let mut enable = true;
+        for flag in flags.chars() {
+            match flag {
+                '-' => enable = false,
+                'x' => self.flags.verbose = enable,
+                's' => self.flags.dot_all = enable,
+                _ => {}
+            }
+        }
+    }
+
+    /// Translate bracket expression, its `[` being already consumed.
+    fn bracket(&mut self, start: usize) -> Result<(), ParseError> {
+        self.output.push('[');
+        if self.chars.next_if(|(_, c)| *c == '^').is_some() {
+            self.output.push('^');
+        }
+
+        // Closing bracket right after the opening one is literal.
+        let mut first = true;
+        loop {
+            let (idx, c) = self
+                .chars
+                .next()
+                .ok_or_else(|| self.error(ParseErrorKind::UnclosedBracket, start, start + 1))?;
+            self.offsets.push(self.output.len(), idx);
+            let c = match c {
+                ']' if !first => break,
+                '[' if self.pattern[idx..].starts_with("[:") => {
+                    let end = self.pattern[idx..]
+                        .find(":]")
+                        .map_or(idx + 2, |end| idx + end + 2);
+                    return Err(self.unsupported(idx, end));
+                }
+                '\\' => match self.bracket_escape(idx)? {
+                    Some(c) => c,
+                    None => {
+                        first = false;
+                        continue;
+                    }
+                },
+                c => c,
+            };
+            first = false;
+
+            // Ranges are expanded to the chars they contain.
+            let is_range = self.chars.peek().is_some_and(|(dash_idx, next)| {
+                *next == '-' && !self.pattern[dash_idx + 1..].starts_with(']')
+            });
+            if !is_range {
+                push_literal(&mut self.output, c);
+                continue;
+            }
+
+            self.chars.next();
+            let (end_idx, end) = self
+                .chars
+                .next()
+                .ok_or_else(|| self.error(ParseErrorKind::UnclosedBracket, start, start + 1))?;
+            let end = match end {
+                '\\' => match self.bracket_escape(end_idx)? {
+                    Some(end) => end,
+                    // Classes like `\d` cannot end a range.
+                    None => {
+                        let range_end = self.position();
+                        return Err(self.unsupported(idx, range_end));
+                    }
+                },
+                end => end,
+            };
+            if end < c {
+                let range_end = self.position();
+                return Err(self.error(ParseErrorKind::InvalidRange, idx, range_end));
+            }
+            if u32::from(end) - u32::from(c) >= MAX_RANGE_LEN {
+                let range_end = self.position();
+                return Err(self.unsupported(idx, range_end));
+            }
+            (c..=end).for_each(|c| push_literal(&mut self.output, c));
+        }
+
+        self.output.push(']');
+        Ok(())
+    }
+
+    /// Translate escape in a bracket expression, returning the char it stands for if any.
+    fn bracket_escape(&mut self, start: usize) -> Result<Option<char>, ParseError> {
+        let (idx, c) = self
+            .chars
+            .next()
+            .ok_or_else(|| self.error(ParseErrorKind::TrailingBackslash, start, start + 1))?;
+        match c {
+            'd' => self.output.push_str("0123456789"),
+            'w' => self.output.push_str(WORD_CHARS),
+            's' => self.output.push_str(r"\p{White_Space}"),
+            'h' => self.output.push_str(HORIZONTAL_SPACES),
+            'v' => self.output.push_str(VERTICAL_SPACES),
+            'p' | 'P' => {
+                let len = self.property_len(idx + 1)?;
+                self.output.push_str(&self.pattern[start..idx + 1 + len]);
+                self.skip_to(idx + len + 1);
+            }
+            // Word boundary is a backspace in brackets.
+            'b' => return Ok(Some('\u{8}')),
+            c => return self.escaped_char(start, c).map(Some),
+        }
+        Ok(None)
+    }
+
+    /// Push back reference to group `number`.
+    fn back_reference(&mut self, start: usize, number: usize) -> Result<(), ParseError> {
+        let end = self.position();
+        // Extended syntax only has single digit references, `\1` to `\9`.
+        match number {
+            1..=9 => {
+                self.output.push_str(&format!(r"\{number}"));
+                Ok(())
+            }
+            0 => Err(self.error(ParseErrorKind::InvalidGroupReference, start, end)),
+            _ => Err(self.unsupported(start, end)),
+        }
+    }
+
+    /// Resolve group number, name or relative `-N` reference of escape starting at `start`.
+    fn group_reference(&mut self, reference: &str, start: usize) -> Result<usize, ParseError> {
+        if let Some(offset) = reference.strip_prefix('-') {
+            let end = self.position();
+            let invalid = self.error(ParseErrorKind::InvalidGroupReference, start, end);
+            let offset: usize = offset.parse().map_err(|_| invalid.clone())?;
+            return (self.group_count + 1).checked_sub(offset).ok_or(invalid);
+        }
+        match reference.parse() {
+            Ok(number) => Ok(number),
+            Err(_) => self.named_group(reference, start),
+        }
+    }
+
+    /// Resolve group name of reference starting at `start`, which has been consumed.
+    fn named_group(&mut self, name: &str, start: usize) -> Result<usize, ParseError> {
+        match self.names.get(name) {
+            Some(number) => Ok(*number),
+            None => {
+                let end = self.position();
+                let kind = ParseErrorKind::UnknownGroupName(name.to_string());
+                Err(self.error(kind, start, end))
+            }
+        }
+    }
+
+    /// Take name in `{...}`, `<...>` or `'...'` following an escape starting at `start`.
+    fn take_delimited(&mut self, start: usize) -> Result<&'a str, ParseError> {
+        let (idx, open) = self.chars.next().ok_or_else(|| {
+            let end = self.pattern.len();
+            self.error(ParseErrorKind::InvalidEscape, start, end)
+        })?;
+        let close = match open {
+            '{' => '}',
+            '<' => '>',
+            '\'' => '\'',
+            _ => return Err(self.unsupported(start, idx + open.len_utf8())),
+        };
+
+        let pattern = self.pattern;
+        let len = pattern[idx + 1..]
+            .find(close)
+            .ok_or_else(|| self.error(ParseErrorKind::InvalidEscape, start, pattern.len()))?;
+        self.skip_to(idx + 1 + len + 1);
+        Ok(&pattern[idx + 1..idx + 1 + len])
+    }
+
+    /// Take chars from `start` while they satisfy predicate, the first one being consumed.
+    fn take_while(&mut self, start: usize, predicate: impl Fn(char) -> bool) -> &'a str {
+        let pattern = self.pattern;
+        while self.chars.next_if(|(_, c)| predicate(*c)).is_some() {}
+        let end = self.chars.peek().map_or(pattern.len(), |(idx, _)| *idx);
+        &pattern[start..end]
+    }
+
+    /// Byte index of the next char to translate.
+    fn position(&mut self) -> usize {
+        self.chars
+            .peek()
+            .map_or(self.pattern.len(), |(idx, _)| *idx)
+    }
+
+    /// Consume chars up to byte index `end`, excluded.
+    fn skip_to(&mut self, end: usize) {
+        while self.chars.next_if(|(idx, _)| *idx < end).is_some() {}
+    }
+
+    fn unsupported(&self, start: usize, end: usize) -> ParseError {
+        let end = end.min(self.pattern.len());
+        let kind = ParseErrorKind::UnsupportedSyntax(self.pattern[start..end].to_string());
+        self.error(kind, start, end)
+    }
+
+    fn error(&self, kind: ParseErrorKind, start: usize, end: usize) -> ParseError {
+        ParseError::new(self.pattern, kind, start..end.min(self.pattern.len()))
+    }
+}
+
+/// Push char so it is matched literally, even in brackets or verbose mode.
+fn push_literal(output: &mut String, c: char) {
+    if c.is_ascii() && !c.is_ascii_alphanumeric() {
+        output.push_str(&format!(r"\x{:02X}", u32::from(c)));
+    } else {
+        output.push(c);
+    }
+}
@@ -0,0 +1,263 @@
+use std::collections::HashSet;
+
+use crate::{
+    cancel::{Interrupt, CHECK_INTERVAL},
+    is_char_boundary,
+    literal::Prefilter,
+    program::{Inst, Program},
+    GrepError, MatchResult,
+};
+
+/// Run program over input simulating all NFA threads at once, in O(input * program) time.
+///
+/// Threads are kept ordered by priority so the result is the same leftmost-first match a
+/// backtracker would find. When `char_boundaries` is set, matches only start on UTF-8
+/// char boundaries. Prefilter is used to skip input where no match can start.
+pub(crate) fn find(
+    program: &Program,
+    prefilter: Option<&Prefilter>,
+    input: &[u8],
+    start: usize,
+    char_boundaries: bool,
+    interrupt: &Interrupt,
+) -> Result<Option<MatchResult>, GrepError> {
+    let mut vm = PikeVm {
+        program,
+        input,
+        current: ThreadList::default(),
+        next: ThreadList::default(),
+    };
+    let mut found = None;
+    let mut steps: usize = 0;
+
+    let mut index = start;
+    while index <= input.len() {
+        if steps.is_multiple_of(CHECK_INTERVAL) {
+            interrupt.check()?;
+        }
+        steps += 1;
+
+        // Jump to next candidate start when there is no thread to run.
+        if let Some(prefilter) = prefilter.filter(|_| vm.current.threads.is_empty()) {
+            if found.is_some() || program.anchored && index > 0 {
+                break;
+            }
+            match prefilter.next_start(input, index) {
+                Some(start) => index = start,
+                None => break,
+            }
+        }
+
+        // Start a new lowest priority thread until a match is found.
+        let can_start = !program.anchored || index == 0;
+        let on_boundary = !char_boundaries || is_char_boundary(input, index);
+        let is_start = prefilter.is_none_or(|prefilter| prefilter.is_start(input, index));
+        if found.is_none() && can_start && on_boundary && is_start {
+            let slots = vec![None; program.slot_count];
+            vm.add_thread(Side::Current, 0, index, slots);
+        }
+
+        if vm.current.threads.is_empty() {
+            if found.is_some() || program.anchored {
+                break;
+            }
+            index += 1;
+            continue;
+        }
+
+        if let Some(slots) = vm.step(index, |_| true) {
+            found = Some(slots);
+        }
+
+        std::mem::swap(&mut vm.current, &mut vm.next);
+        vm.next.clear();
+        index += 1;
+    }
+
+    Ok(found.and_then(|slots| Some((slots[0]?, slots[1]?))))
+}
+
+/// Find end of the longest match starting exactly at `start`, for leftmost-longest semantics.
+///
+/// Threads are never dropped on match, the last match found being the longest.
+pub(crate) fn longest_end(
+    program: &Program,
+    input: &[u8],
+    start: usize,
+    interrupt: &Interrupt,
+) -> Result<Option<usize>, GrepError> {
+    let mut vm = PikeVm {
+        program,
+        input,
+        current: ThreadList::default(),
+        next: ThreadList::default(),
+    };
+    let mut end = None;
+
+    vm.add_thread(Side::Current, 0, start, vec![None; program.slot_count]);
+    for index in start..=input.len() {
+        if vm.current.threads.is_empty() {
+            break;
+        }
+        if (index - start).is_multiple_of(CHECK_INTERVAL) {
+            interrupt.check()?;
+        }
+
+        vm.step(index, |_| {
+            end = Some(index);
+            false
+        });
+
+        std::mem::swap(&mut vm.current, &mut vm.next);
+        vm.next.clear();
+    }
+
+    Ok(end)
+}
+
+/// Run program compiled from a regex set, returning which of its patterns match in input.
+///
+/// Threads are never dropped on match so every pattern is found in a single pass.
+pub(crate) fn find_set(
+    program: &Program,
+    input: &[u8],
+    pattern_count: usize,
+    char_boundaries: bool,
+) -> Vec<bool> {
+    let mut vm = PikeVm {
+        program,
+        input,
+        current: ThreadList::default(),
+        next: ThreadList::default(),
+    };
+    let mut matched = vec![false; pattern_count];
+    let mut remaining = program
+        .insts
+        .iter()
+        .filter(|inst| matches!(inst, Inst::Match(_)))
+        .count();
+
+    for index in 0..=input.len() {
+        if !char_boundaries || is_char_boundary(input, index) {
+            let slots = vec![None; program.slot_count];
+            vm.add_thread(Side::Current, 0, index, slots);
+        }
+
+        vm.step(index, |pattern| {
+            if !matched[pattern] {
+                matched[pattern] = true;
+                remaining -= 1;
+            }
+            false
+        });
+        if remaining == 0 {
+            break;
+        }
+
+        std::mem::swap(&mut vm.current, &mut vm.next);
+        vm.next.clear();
+    }
+
+    matched
+}
+
+type Slots = Vec<Option<usize>>;
+
+struct Thread {
+    pc: usize,
+    /// Position at which the thread can run, after the end of the last consumed char.
+    index: usize,
+    slots: Slots,
+}
+
+#[derive(Default)]
+struct ThreadList {
+    threads: Vec<Thread>,
+    seen: HashSet<(usize, usize)>,
+}
+
+impl ThreadList {
+    fn clear(&mut self) {
+        self.threads.clear();
+        self.seen.clear();
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Side {
+    Current,
+    Next,
+}
+
+struct PikeVm<'a> {
+    program: &'a Program,
+    input: &'a [u8],
+    current: ThreadList,
+    next: ThreadList,
+}
+
+impl PikeVm<'_> {
+    /// Advance every thread ready at `index`, calling `on_match` with index of matched patterns.
+    ///
+    /// Returns slots of the first match for which `on_match` returns `true`, dropping lower
+    /// priority threads.
+    fn step(&mut self, index: usize, mut on_match: impl FnMut(usize) -> bool) -> Option<Slots> {
+        let threads = std::mem::take(&mut self.current.threads);
+
+        for thread in threads {
+            // Thread is still in the middle of a multi bytes char, keep its priority.
+            if thread.index > index {
+                if self.next.seen.insert((thread.pc, thread.index)) {
+                    self.next.threads.push(thread);
+                }
+                continue;
+            }
+
+            match &self.program.insts[thread.pc] {
+                Inst::Match(pattern) if on_match(*pattern) => return Some(thread.slots),
+                Inst::Unit(pattern) => {
+                    if let Some(len) = pattern.match_unit(&self.input[index..]) {
+                        self.add_thread(Side::Next, thread.pc + 1, index + len, thread.slots);
+                    }
+                }
+                // Other instructions are followed when adding threads.
+                _ => {}
+            }
+        }
+
+        None
+    }
+
+    /// Follow every empty transition from `pc` and add reached threads in priority order.
+    fn add_thread(&mut self, side: Side, pc: usize, index: usize, slots: Slots) {
+        let mut stack = vec![(pc, slots)];
+
+        while let Some((pc, mut slots)) = stack.pop() {
+            let list = match side {
+                Side::Current => &mut self.current,
+                Side::Next => &mut self.next,
+            };
+            if !list.seen.insert((pc, index)) {
+                continue;
+            }
+
+            match &self.program.insts[pc] {
+                Inst::Unit(_) | Inst::Match(_) => list.threads.push(Thread { pc, index, slots }),
+                Inst::Assert(pattern) => {
+                    if pattern.match_anchor(self.input, index) {
+                        stack.push((pc + 1, slots));
+                    }
+                }
+                Inst::Split(first, second) => {
+                    stack.push((*second, slots.clone()));
+                    stack.push((*first, slots));
+                }
+                Inst::Jump(target) => stack.push((*target, slots)),
+                Inst::Save(slot) => {
+                    slots[*slot] = Some(index);
+                    stack.push((pc + 1, slots));
+                }
+            }
+        }
+    }
+}
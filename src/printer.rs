@@ -0,0 +1,380 @@
+use std::{
+    fmt::{self, Write},
+    slice,
+};
+
+use crate::{ClassItem, Flags, Pattern, Regexp};
+
+/// Chars having a meaning in patterns, escaped when printed as literals.
+const META_CHARS: &str = r"\.+*?()|[]{}^$";
+
+/// Print regex as a canonical pattern, parsed back to the same patterns by
+/// [`re_parse`](crate::re_parse) when they come from parsing.
+impl fmt::Display for Regexp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Printer::new(f, self.patterns()).sequence(self.patterns())
+    }
+}
+
+/// Print pattern in canonical syntax, see [`Regexp`] display.
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Printer::new(f, slice::from_ref(self)).pattern(self)
+    }
+}
+
+/// Pattern printer, tracking flags in effect like the parser so they are only set inline
+/// where needed, like `(?-u)` before a byte, and group ids like the parser numbers them.
+struct Printer<'a, 'f> {
+    f: &'a mut fmt::Formatter<'f>,
+    flags: Flags,
+    /// Sorted ids of groups in printed patterns.
+    ids: Vec<usize>,
+    /// Id the parser gives to the next group.
+    next_id: usize,
+}
+
+impl<'a, 'f> Printer<'a, 'f> {
+    fn new(f: &'a mut fmt::Formatter<'f>, patterns: &[Pattern]) -> Self {
+        let mut ids = Vec::new();
+        capture_ids(patterns, &mut ids);
+        ids.sort_unstable();
+        ids.dedup();
+        Self {
+            f,
+            flags: Flags::default(),
+            ids,
+            next_id: 1,
+        }
+    }
+
+    fn sequence(&mut self, patterns: &[Pattern]) -> fmt::Result {
+        patterns
+            .iter()
+            .try_for_each(|pattern| self.pattern(pattern))
+    }
+
+    fn pattern(&mut self, pattern: &Pattern) -> fmt::Result {
+        if let Some(counted) = Counted::find(pattern) {
+            return self.counted(counted);
+        }
+
+        match pattern {
+            Pattern::Literal(c) => {
+                self.set_flags(|flags| flags.case_insensitive = false)?;
+                if META_CHARS.contains(*c) {
+                    self.f.write_char('\\')?;
+                }
+                self.f.write_char(*c)
+            }
+            Pattern::Digit => self.unicode(r"\d"),
+            Pattern::Chars => self.unicode(r"\w"),
+            Pattern::PositiveCharGroup(chars) | Pattern::NegativeCharGroup(chars) => {
+                self.set_flags(|flags| {
+                    flags.case_insensitive = false;
+                    flags.bytes = false;
+                })?;
+                self.open_bracket(matches!(pattern, Pattern::NegativeCharGroup(_)))?;
+                chars.iter().try_for_each(|c| self.bracket_char(*c))?;
+                self.f.write_char(']')
+            }
+            Pattern::Property { property, negated } => {
+                self.set_flags(|flags| {
+                    flags.case_insensitive = property.is_case_insensitive();
+                    flags.bytes = false;
+                })?;
+                let escape = if *negated { 'P' } else { 'p' };
+                write!(self.f, r"\{escape}{{{}}}", property.name())
+            }
+            Pattern::CharClass { items, negated } => {
+                // Chars of case insensitive classes already are all their case variants.
+                let case_insensitive = items.iter().any(|item| {
+                    matches!(item, ClassItem::Property { property, .. } if property.is_case_insensitive())
+                });
+                self.set_flags(|flags| {
+                    flags.case_insensitive = case_insensitive;
+                    flags.bytes = false;
+                })?;
+                self.open_bracket(*negated)?;
+                for item in items {
+                    match item {
+                        ClassItem::Char(c) => self.bracket_char(*c)?,
+                        ClassItem::Property { property, negated } => {
+                            let escape = if *negated { 'P' } else { 'p' };
+                            write!(self.f, r"\{escape}{{{}}}", property.name())?;
+                        }
+                    }
+                }
+                self.f.write_char(']')
+            }
+            Pattern::Byte(byte) => {
+                self.set_flags(|flags| flags.bytes = true)?;
+                write!(self.f, r"\x{byte:02X}")
+            }
+            Pattern::ByteClass { bytes, negated } => {
+                self.set_flags(|flags| {
+                    flags.case_insensitive = false;
+                    flags.bytes = true;
+                })?;
+                self.open_bracket(*negated)?;
+                for byte in bytes {
+                    write!(self.f, r"\x{byte:02X}")?;
+                }
+                self.f.write_char(']')
+            }
+            Pattern::Start => self.anchor(false, '^'),
+            Pattern::End => self.anchor(false, '$'),
+            Pattern::LineStart => self.anchor(true, '^'),
+            Pattern::LineEnd => self.anchor(true, '$'),
+            Pattern::EndBeforeNewline => self.f.write_str(r"\Z"),
+            Pattern::ResetStart => self.f.write_str(r"\K"),
+            Pattern::Wildcard | Pattern::AnyChar => {
+                let dot_all = matches!(pattern, Pattern::AnyChar);
+                self.set_flags(|flags| {
+                    flags.dot_all = dot_all;
+                    flags.bytes = false;
+                })?;
+                self.f.write_char('.')
+            }
+            Pattern::OneOrMore(pattern) => self.repeated(pattern, "+"),
+            Pattern::LazyOneOrMore(pattern) => self.repeated(pattern, "+?"),
+            // A `?` after a greedy quantifier would make it lazy instead.
+            Pattern::ZeroOrOne(pattern) => match &**pattern {
+                Pattern::OneOrMore(pattern) => self.repeated(pattern, "*"),
+                pattern if is_greedy(pattern) => self.repeated(pattern, "{0,1}"),
+                pattern => self.repeated(pattern, "?"),
+            },
+            Pattern::LazyZeroOrOne(pattern) => match &**pattern {
+                Pattern::LazyOneOrMore(pattern) => self.repeated(pattern, "*?"),
+                pattern if is_greedy(pattern) => self.repeated(pattern, "{0,1}?"),
+                pattern => self.repeated(pattern, "??"),
+            },
+            Pattern::Alternation { alternations, id } => {
+                self.next_id = id + 1;
+                self.group("(", alternations)
+            }
+            Pattern::Group { alternations } if *alternations == [Vec::new()] => self.empty_group(),
+            Pattern::Group { alternations } => self.group("(?:", alternations),
+            Pattern::BackReference(number) => write!(self.f, r"\{number}"),
+            Pattern::LookAhead {
+                alternations,
+                negative,
+            } => self.group(if *negative { "(?!" } else { "(?=" }, alternations),
+            Pattern::LookBehind {
+                alternations,
+                negative,
+            } => self.group(if *negative { "(?<!" } else { "(?<=" }, alternations),
+        }
+    }
+
+    /// Write counted repetition once, as copies of its groups would get new ids.
+    fn counted(&mut self, counted: Counted) -> fmt::Result {
+        self.pattern(counted.pattern)?;
+        match counted.max {
+            Some(max) if max == counted.min => return write!(self.f, "{{{max}}}"),
+            Some(max) => write!(self.f, "{{{},{max}}}", counted.min)?,
+            None => write!(self.f, "{{{},}}", counted.min)?,
+        }
+        if counted.lazy {
+            self.f.write_char('?')?;
+        }
+        Ok(())
+    }
+
+    /// Write group repeated zero times, the only way to parse an empty group as `(?:)` is
+    /// rejected, with the groups it dropped so later groups keep their ids.
+    fn empty_group(&mut self) -> fmt::Result {
+        let next_id = self.next_id;
+        let dropped = match self.ids.iter().find(|id| **id >= next_id) {
+            Some(id) => id - next_id,
+            None => 0,
+        };
+        self.next_id += dropped;
+        match dropped {
+            0 => self.f.write_str("a{0}"),
+            1 => self.f.write_str("(a){0}"),
+            _ => write!(self.f, "(?:{}){{0}}", "(a)".repeat(dropped)),
+        }
+    }
+
+    fn unicode(&mut self, escape: &str) -> fmt::Result {
+        self.set_flags(|flags| flags.bytes = false)?;
+        self.f.write_str(escape)
+    }
+
+    fn anchor(&mut self, multi_line: bool, anchor: char) -> fmt::Result {
+        self.set_flags(|flags| flags.multi_line = multi_line)?;
+        self.f.write_char(anchor)
+    }
+
+    fn repeated(&mut self, pattern: &Pattern, quantifier: &str) -> fmt::Result {
+        self.pattern(pattern)?;
+        self.f.write_str(quantifier)
+    }
+
+    fn group(&mut self, open: &str, alternations: &[Vec<Pattern>]) -> fmt::Result {
+        let flags = self.flags;
+        self.f.write_str(open)?;
+        for (idx, alternation) in alternations.iter().enumerate() {
+            if idx > 0 {
+                self.f.write_char('|')?;
+            }
+            self.sequence(alternation)?;
+        }
+
+        // Flags set in the group do not apply after it.
+        self.flags = flags;
+        self.f.write_char(')')
+    }
+
+    fn open_bracket(&mut self, negated: bool) -> fmt::Result {
+        self.f.write_str(if negated { "[^" } else { "[" })
+    }
+
+    /// Write bracket char, in hex when it could end the bracket or start an escape.
+    fn bracket_char(&mut self, c: char) -> fmt::Result {
+        if matches!(c, ']' | '[' | '\\' | '^') {
+            write!(self.f, r"\x{:02X}", u32::from(c))
+        } else {
+            self.f.write_char(c)
+        }
+    }
+
+    /// Set inline flags needed by the next pattern, if they are not already in effect.
+    fn set_flags(&mut self, update: impl FnOnce(&mut Flags)) -> fmt::Result {
+        let mut flags = self.flags;
+        update(&mut flags);
+
+        let mut enabled = String::new();
+        let mut disabled = String::new();
+        for (flag, was, is) in [
+            ('i', self.flags.case_insensitive, flags.case_insensitive),
+            ('m', self.flags.multi_line, flags.multi_line),
+            ('s', self.flags.dot_all, flags.dot_all),
+            ('u', !self.flags.bytes, !flags.bytes),
+        ] {
+            match (was, is) {
+                (false, true) => enabled.push(flag),
+                (true, false) => disabled.push(flag),
+                _ => {}
+            }
+        }
+        self.flags = flags;
+
+        match (enabled.is_empty(), disabled.is_empty()) {
+            (true, true) => Ok(()),
+            (_, true) => write!(self.f, "(?{enabled})"),
+            _ => write!(self.f, "(?{enabled}-{disabled})"),
+        }
+    }
+}
+
+/// Counted repetition `{min,max}` of a pattern with groups, which parsing expands to copies
+/// sharing the group ids.
+struct Counted<'p> {
+    pattern: &'p Pattern,
+    min: usize,
+    max: Option<usize>,
+    lazy: bool,
+}
+
+impl<'p> Counted<'p> {
+    /// Find counted repetition in the form parsing expands it to, like `(?:pp(?:p(?:p)?)?)`
+    /// for `p{2,4}` or `(?:pp+)` for `p{3,}`.
+    fn find(pattern: &'p Pattern) -> Option<Self> {
+        let counted = match pattern {
+            Pattern::Group { alternations } => {
+                let [sequence] = &alternations[..] else {
+                    return None;
+                };
+                let repeated = sequence.first()?;
+                let min = sequence.iter().take_while(|p| *p == repeated).count();
+                let (max, lazy) = match &sequence[min..] {
+                    [] => (Some(min), false),
+                    [Pattern::OneOrMore(last)] if **last == *repeated => (None, false),
+                    [Pattern::LazyOneOrMore(last)] if **last == *repeated => (None, true),
+                    [optional] => match optional_copies(optional)? {
+                        (last, count, lazy) if last == repeated => (Some(min + count), lazy),
+                        _ => return None,
+                    },
+                    _ => return None,
+                };
+                let min = if max.is_none() { min + 1 } else { min };
+                Self {
+                    pattern: repeated,
+                    min,
+                    max,
+                    lazy,
+                }
+            }
+            pattern => {
+                let (repeated, count, lazy) = optional_copies(pattern)?;
+                Self {
+                    pattern: repeated,
+                    min: 0,
+                    max: Some(count),
+                    lazy,
+                }
+            }
+        };
+
+        // Copies without groups are printed as is, like `(?:aa)` for `a{2}`.
+        let copies = counted.max.unwrap_or(counted.min);
+        let mut ids = Vec::new();
+        capture_ids(slice::from_ref(counted.pattern), &mut ids);
+        (copies > 1 && !ids.is_empty()).then_some(counted)
+    }
+}
+
+/// Count nested optional copies `(?:p(?:p)?)?` of a pattern ending a counted repetition,
+/// along with their laziness.
+fn optional_copies(pattern: &Pattern) -> Option<(&Pattern, usize, bool)> {
+    let (inner, lazy) = match pattern {
+        Pattern::ZeroOrOne(inner) => (&**inner, false),
+        Pattern::LazyZeroOrOne(inner) => (&**inner, true),
+        _ => return None,
+    };
+    if let Pattern::Group { alternations } = inner {
+        if let [sequence] = &alternations[..] {
+            if let [repeated, rest] = &sequence[..] {
+                match optional_copies(rest) {
+                    Some((last, count, rest_lazy)) if last == repeated && rest_lazy == lazy => {
+                        return Some((repeated, count + 1, lazy));
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+    Some((inner, 1, lazy))
+}
+
+/// Add ids of groups in patterns.
+fn capture_ids(patterns: &[Pattern], ids: &mut Vec<usize>) {
+    for pattern in patterns {
+        match pattern {
+            Pattern::Alternation { alternations, id } => {
+                ids.push(*id);
+                alternations.iter().for_each(|alt| capture_ids(alt, ids));
+            }
+            Pattern::Group { alternations }
+            | Pattern::LookAhead { alternations, .. }
+            | Pattern::LookBehind { alternations, .. } => {
+                alternations.iter().for_each(|alt| capture_ids(alt, ids));
+            }
+            Pattern::OneOrMore(pattern)
+            | Pattern::LazyOneOrMore(pattern)
+            | Pattern::ZeroOrOne(pattern)
+            | Pattern::LazyZeroOrOne(pattern) => capture_ids(slice::from_ref(&**pattern), ids),
+            _ => {}
+        }
+    }
+}
+
+/// Check if pattern is a greedy quantifier, which a following `?` would make lazy.
+fn is_greedy(pattern: &Pattern) -> bool {
+    match Counted::find(pattern) {
+        Some(counted) => !counted.lazy,
+        None => matches!(pattern, Pattern::OneOrMore(_) | Pattern::ZeroOrOne(_)),
+    }
+}
@@ -0,0 +1,170 @@
+use crate::Pattern;
+
+/// Instruction of a compiled Thompson NFA.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Inst {
+    /// Consume a single char or byte matching the unit pattern.
+    Unit(Pattern),
+    /// Zero width anchor that must hold at current position.
+    Assert(Pattern),
+    /// Try both branches, first one having priority.
+    Split(usize, usize),
+    Jump(usize),
+    /// Record current position in capture slot.
+    Save(usize),
+    /// Pattern with given index in its set matched, always `0` outside of sets.
+    Match(usize),
+}
+
+/// Compiled pattern sequence, slots `0` and `1` being the whole match bounds.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Program {
+    pub(crate) insts: Vec<Inst>,
+    pub(crate) slot_count: usize,
+    /// Program can only match at start of input.
+    pub(crate) anchored: bool,
+}
+
+impl Program {
+    /// Compile patterns, `None` if they need backtracking (back references, lookaround or `\K`).
+    pub(crate) fn compile(patterns: &[Pattern]) -> Option<Self> {
+        let mut compiler = Compiler::default();
+
+        compiler.push(Inst::Save(0));
+        compiler.sequence(patterns)?;
+        compiler.push(Inst::Save(1));
+        compiler.push(Inst::Match(0));
+
+        Some(Self {
+            insts: compiler.insts,
+            slot_count: compiler.slot_count,
+            anchored: patterns.first() == Some(&Pattern::Start),
+        })
+    }
+
+    /// Compile many pattern sequences in a single program, tried in parallel.
+    ///
+    /// Sequences needing backtracking are left out, `None` being returned if there is no
+    /// other one.
+    pub(crate) fn compile_set<'a>(
+        sequences: impl IntoIterator<Item = &'a [Pattern]>,
+    ) -> Option<Self> {
+        let compilable: Vec<_> = sequences
+            .into_iter()
+            .enumerate()
+            .filter(|(_, patterns)| Self::compile(patterns).is_some())
+            .collect();
+
+        let mut compiler = Compiler::default();
+        for (position, (idx, patterns)) in compilable.iter().enumerate() {
+            let split = (position + 1 < compilable.len()).then(|| compiler.push(Inst::Split(0, 0)));
+
+            compiler.sequence(patterns)?;
+            compiler.push(Inst::Match(*idx));
+
+            if let Some(split) = split {
+                compiler.insts[split] = Inst::Split(split + 1, compiler.insts.len());
+            }
+        }
+
+        (!compilable.is_empty()).then_some(Self {
+            insts: compiler.insts,
+            slot_count: compiler.slot_count,
+            anchored: false,
+        })
+    }
+}
+
+struct Compiler {
+    insts: Vec<Inst>,
+    slot_count: usize,
+}
+
+impl Default for Compiler {
+    fn default() -> Self {
+        Self {
+            insts: Vec::new(),
+            slot_count: 2,
+        }
+    }
+}
+
+impl Compiler {
+    fn push(&mut self, inst: Inst) -> usize {
+        self.insts.push(inst);
+        self.insts.len() - 1
+    }
+
+    fn sequence(&mut self, patterns: &[Pattern]) -> Option<()> {
+        patterns
+            .iter()
+            .try_for_each(|pattern| self.pattern(pattern))
+    }
+
+    fn pattern(&mut self, pattern: &Pattern) -> Option<()> {
+        match pattern {
+            pattern if pattern.is_unit() => {
+                self.push(Inst::Unit(pattern.clone()));
+            }
+            pattern if pattern.is_anchor() => {
+                self.push(Inst::Assert(pattern.clone()));
+            }
+            Pattern::OneOrMore(pattern) => {
+                let start = self.insts.len();
+                self.pattern(pattern)?;
+                let split = self.push(Inst::Split(start, 0));
+                self.insts[split] = Inst::Split(start, split + 1);
+            }
+            Pattern::ZeroOrOne(pattern) => {
+                let split = self.push(Inst::Split(0, 0));
+                self.pattern(pattern)?;
+                self.insts[split] = Inst::Split(split + 1, self.insts.len());
+            }
+            Pattern::LazyOneOrMore(pattern) => {
+                let start = self.insts.len();
+                self.pattern(pattern)?;
+                let split = self.push(Inst::Split(0, start));
+                self.insts[split] = Inst::Split(split + 1, start);
+            }
+            Pattern::LazyZeroOrOne(pattern) => {
+                let split = self.push(Inst::Split(0, 0));
+                self.pattern(pattern)?;
+                self.insts[split] = Inst::Split(self.insts.len(), split + 1);
+            }
+            Pattern::Alternation { alternations, id } => {
+                self.slot_count = self.slot_count.max(2 * id + 2);
+                self.push(Inst::Save(2 * id));
+                self.alternation(alternations)?;
+                self.push(Inst::Save(2 * id + 1));
+            }
+            Pattern::Group { alternations } => self.alternation(alternations)?,
+            // Back references and lookaround need to look at what has been matched or around,
+            // `\K` to move the start of the match.
+            _ => return None,
+        }
+
+        Some(())
+    }
+
+    fn alternation(&mut self, alternations: &[Vec<Pattern>]) -> Option<()> {
+        let mut jumps = Vec::with_capacity(alternations.len());
+
+        for (idx, alt) in alternations.iter().enumerate() {
+            if idx + 1 == alternations.len() {
+                self.sequence(alt)?;
+            } else {
+                let split = self.push(Inst::Split(0, 0));
+                self.sequence(alt)?;
+                jumps.push(self.push(Inst::Jump(0)));
+                self.insts[split] = Inst::Split(split + 1, self.insts.len());
+            }
+        }
+
+        let end = self.insts.len();
+        for jump in jumps {
+            self.insts[jump] = Inst::Jump(end);
+        }
+
+        Some(())
+    }
+}
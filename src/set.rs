@@ -0,0 +1,80 @@
+use crate::{pikevm, program::Program, re_parse, GrepError, Regexp};
+
+/// Many regexes compiled together, to find all the ones matching an input at once.
+///
+/// Regexes are run in parallel over a single pass of input, except those with back
+/// references or lookaround that are still backtracked one by one.
+#[derive(Debug, Clone)]
+pub struct RegexSet {
+    regexps: Vec<Regexp>,
+    program: Option<Program>,
+    /// Indexes of regexes left out of the program, which need to be backtracked.
+    backtracked: Vec<usize>,
+}
+
+impl RegexSet {
+    pub fn new<I, S>(patterns: I) -> Result<Self, GrepError>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let regexps = patterns
+            .into_iter()
+            .map(|pattern| re_parse(pattern.as_ref()))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Self::from_regexps(regexps))
+    }
+
+    /// Build set from already parsed regexes, for ones needing custom flags.
+    pub fn from_regexps(regexps: Vec<Regexp>) -> Self {
+        let program = Program::compile_set(regexps.iter().map(Regexp::patterns));
+        let backtracked = (0..regexps.len())
+            .filter(|idx| regexps[*idx].is_backtracked())
+            .collect();
+
+        Self {
+            regexps,
+            program,
+            backtracked,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.regexps.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.regexps.is_empty()
+    }
+
+    /// Check if any regex matches input.
+    pub fn is_match(&self, input: &str) -> bool {
+        !self.matches(input).is_empty()
+    }
+
+    /// Sorted indexes of regexes matching input.
+    pub fn matches(&self, input: &str) -> Vec<usize> {
+        self.find_matches(input.as_bytes(), true)
+    }
+
+    /// Sorted indexes of regexes matching input, which does not need to be valid UTF-8.
+    pub fn matches_bytes(&self, input: &[u8]) -> Vec<usize> {
+        self.find_matches(input, false)
+    }
+
+    fn find_matches(&self, input: &[u8], char_boundaries: bool) -> Vec<usize> {
+        let mut matched = match &self.program {
+            Some(program) => pikevm::find_set(program, input, self.len(), char_boundaries),
+            None => vec![false; self.len()],
+        };
+
+        for idx in &self.backtracked {
+            matched[*idx] = self.regexps[*idx]
+                .find_from(input, 0, char_boundaries)
+                .is_ok_and(|found| found.is_some());
+        }
+
+        (0..self.len()).filter(|idx| matched[*idx]).collect()
+    }
+}
@@ -0,0 +1,143 @@
+use std::{cmp::Ordering, collections::HashMap, sync::OnceLock};
+
+use crate::unicode_tables::{PROPERTIES, PROPERTY_ALIASES};
+
+/// Unicode general category, script or binary property usable with `\p{...}`.
+#[derive(Debug, Clone, Copy)]
+pub struct UnicodeProperty {
+    name: &'static str,
+    ranges: &'static [(char, char)],
+    case_insensitive: bool,
+}
+
+impl UnicodeProperty {
+    /// Lookup property by name or alias, like `L`, `Letter`, `Greek`, `sc=Grek` or `White_Space`.
+    ///
+    /// Names are matched loosely, ignoring case, spaces, `_` and `-`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        let name = match name.split_once('=') {
+            Some((key, value)) => match normalize(key).as_str() {
+                "gc" | "generalcategory" | "sc" | "script" => value,
+                _ => return None,
+            },
+            None => name,
+        };
+
+        let alias = normalize(name);
+        let index = PROPERTY_ALIASES
+            .binary_search_by_key(&alias.as_str(), |(alias, _)| alias)
+            .ok()?;
+        let canonical = PROPERTY_ALIASES[index].1;
+
+        let index = PROPERTIES
+            .binary_search_by_key(&canonical, |(name, _)| name)
+            .ok()?;
+        let (name, ranges) = PROPERTIES[index];
+
+        Some(Self {
+            name,
+            ranges,
+            case_insensitive: false,
+        })
+    }
+
+    /// Canonical property name.
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Same property also matching chars whose case variants have it, like `(?i)\p{Lu}`.
+    pub fn case_insensitive(self) -> Self {
+        Self {
+            case_insensitive: true,
+            ..self
+        }
+    }
+
+    pub fn is_case_insensitive(&self) -> bool {
+        self.case_insensitive
+    }
+
+    pub fn contains(&self, c: char) -> bool {
+        self.contains_exactly(c)
+            || self.case_insensitive && case_orbit(c).into_iter().any(|c| self.contains_exactly(c))
+    }
+
+    fn contains_exactly(&self, c: char) -> bool {
+        self.ranges
+            .binary_search_by(|(start, end)| {
+                if *end < c {
+                    Ordering::Less
+                } else if *start > c {
+                    Ordering::Greater
+                } else {
+                    Ordering::Equal
+                }
+            })
+            .is_ok()
+    }
+}
+
+impl PartialEq for UnicodeProperty {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name && self.case_insensitive == other.case_insensitive
+    }
+}
+
+impl Eq for UnicodeProperty {}
+
+fn normalize(name: &str) -> String {
+    name.chars()
+        .filter(|c| !matches!(c, ' ' | '_' | '-'))
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// Chars equivalent to `c` under simple case folding, starting with `c`, like `k`, `K` and
+/// the Kelvin sign `K`, or `σ`, `Σ` and final `ς`.
+pub(crate) fn case_orbit(c: char) -> Vec<char> {
+    let mut orbit = vec![c];
+    let mut index = 0;
+    while let Some(&c) = orbit.get(index) {
+        let mappings = [simple(c.to_lowercase()), simple(c.to_uppercase())];
+        let inverse = inverse_case_mappings().get(&c).into_iter().flatten();
+        for variant in mappings.into_iter().flatten().chain(inverse.copied()) {
+            if !orbit.contains(&variant) {
+                orbit.push(variant);
+            }
+        }
+        index += 1;
+    }
+    orbit
+}
+
+/// Only keep simple case mappings, multi char ones cannot be matched one char at a time.
+fn simple(mut mapping: impl Iterator<Item = char>) -> Option<char> {
+    match (mapping.next(), mapping.next()) {
+        (Some(c), None) => Some(c),
+        _ => None,
+    }
+}
+
+/// Chars mapping to each char when lowercased or uppercased, other than itself.
+fn inverse_case_mappings() -> &'static HashMap<char, Vec<char>> {
+    static INVERSE: OnceLock<HashMap<char, Vec<char>>> = OnceLock::new();
+    INVERSE.get_or_init(|| {
+        let mut inverse: HashMap<char, Vec<char>> = HashMap::new();
+        // Chars with case mappings are all cased, no need to scan the whole of Unicode.
+        for name in ["Lowercase", "Uppercase", "Titlecase_Letter"] {
+            let property = UnicodeProperty::from_name(name).expect("cased property");
+            for c in property.ranges.iter().flat_map(|&(start, end)| start..=end) {
+                for variant in [simple(c.to_lowercase()), simple(c.to_uppercase())] {
+                    match variant {
+                        Some(variant) if variant != c => {
+                            inverse.entry(variant).or_default().push(c)
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        }
+        inverse
+    })
+}
@@ -1,11 +1,15 @@
 use grep_starter_rust::*;
 
 fn assert_match(input: &str, pattern: &str, from: usize, to: usize) {
-    assert_eq!(match_pattern(input, pattern), Some((from, to)));
+    assert_eq!(match_pattern(input, pattern).unwrap(), Some((from, to)));
+}
+
+fn assert_is_match(input: &str, pattern: &str) {
+    assert!(match_pattern(input, pattern).unwrap().is_some());
 }
 
 fn assert_not_match(input: &str, pattern: &str) {
-    assert_eq!(match_pattern(input, pattern), None);
+    assert_eq!(match_pattern(input, pattern).unwrap(), None);
 }
 
 #[test]
@@ -25,6 +29,8 @@ fn test_match_chars() {
     assert_match("alpha-num3ric", r"\w", 0, 1);
     assert_match("foo101", r"\w", 0, 1);
     assert_not_match("$!?", r"\w");
+    assert_match("-_-", r"\w", 1, 2);
+    assert_match("-_-", r"(?-u)\w", 1, 2);
 }
 
 #[test]
@@ -103,6 +109,30 @@ fn test_wildcard() {
     assert_not_match("diig", "^d.?gs?$");
 }
 
+#[test]
+fn test_wildcard_line_terminator() {
+    assert_match("dog\n", "d.+", 0, 3);
+    assert_match("dog\r\n", "d.+", 0, 3);
+    assert_not_match("a\nb", "a.b");
+    assert_not_match("a\rb", "a.b");
+    assert_match("a\nb", "(?s)a.b", 0, 3);
+    assert_match("a\r\nb", "(?s)a..b", 0, 4);
+    assert_match("dog\n", "(?s)d.+", 0, 4);
+    assert_match("a\nb", "(?s:a.)b", 0, 3);
+    assert_not_match("a\nb", "(?s)(?-s:a.)b");
+    assert_eq!(
+        match_pattern_with_flags(
+            "a\nb",
+            "a.b",
+            Flags {
+                dot_all: true,
+                ..Default::default()
+            }
+        ),
+        Ok(Some((0, 3)))
+    );
+}
+
 #[test]
 fn test_alternation() {
     assert_match("cat", "(cat|dog)", 0, 3);
@@ -167,4 +197,688 @@ fn test_nested_back_reference() {
         0,
         40,
     );
+
+    // References to a repeated group match its last iteration.
+    assert_not_match("aba", r"(a|b)+\1");
+    assert_match("abb", r"(a|b)+\1", 0, 3);
+    assert_not_match("1b1", r"(b|^\w)+\1");
+    assert_match("baa", r"(\w)+\1", 0, 3);
+    assert_match("abcc", r"(\w){3}\1", 0, 4);
+}
+
+#[test]
+fn test_look_ahead() {
+    assert_match("user=bob", r"\w+(?==)", 0, 4);
+    assert_not_match("user bob", r"\w+(?==)");
+    assert_match("foo1 foo", r"foo(?!\d)", 5, 8);
+    assert_not_match("foo1", r"foo(?!\d)");
+
+    // Groups captured before the lookahead can be referred to in it.
+    assert_match("hello", r"(\w)(?=\1)", 2, 3);
+    assert_match("aab", r"(\w)(?!\1)", 1, 2);
+    assert_match("aa", r"(a)(\1)", 0, 2);
+}
+
+#[test]
+fn test_look_behind() {
+    assert_match("id=3 user=bob", r"(?<=user=)\w+", 10, 13);
+    assert_not_match("id=3 name=bob", r"(?<=user=)\w+");
+    assert_match("id=3 user=bob", r"(?<=(id|user)=)\w+", 3, 4);
+    assert_match("x5 y6", r"(?<!x)\d", 4, 5);
+    assert_match("x5 y6 7", r"(?<![xy])\d", 6, 7);
+    assert_match("ab", r"(?<=a?)b", 1, 2);
+    assert_not_match("x5", r"(?<!x)5");
+
+    // References have no bounded width, so lookbehind only has them in a lookahead.
+    assert_match("abba", r"(\w)\w(?<=(?=\1).)", 1, 3);
+    assert_not_match("abcd", r"(\w)\w(?<=(?=\1).)");
+}
+
+#[test]
+fn test_inline_flags() {
+    assert_match("Hello World", "(?i)hello", 0, 5);
+    assert_match("Hello World", "(?i)[w]orld", 6, 11);
+    assert_not_match("Hello World", "(?i)[^hlo]ello");
+    assert_match("Hello World", "(?i:h)ello", 0, 5);
+    assert_not_match("HELLO", "(?i:h)ello");
+    assert_not_match("HELLO", "(?i)h(?-i)ello");
+    assert_match("hEllo", "(?i)h(?-i:E)llo", 0, 5);
+    // Case variants are all chars with the same simple case folding, like Kelvin sign for `k`.
+    assert_match("\u{212A}", "(?i)k", 0, 3);
+    assert_match("K", "(?i)\u{212A}", 0, 1);
+    assert_match("ς", "(?i)Σ", 0, 2);
+    assert_match("σ", "(?i)[ς]", 0, 2);
+    assert_match("a", r"(?i)\p{Lu}", 0, 1);
+    assert_match("A", r"(?i)[\p{Ll}]", 0, 1);
+    assert_not_match("a", r"\p{Lu}");
+    assert_not_match("a", r"(?i)\P{Lu}");
+    assert_match("ab\ncd", "(?m)^cd$", 3, 5);
+    assert_match("ab\ncd", "(?m)^ab$", 0, 2);
+    assert_not_match("ab\ncd", "^cd");
+    assert_not_match("a\nb", "a.b");
+    assert_match("a\nb", "(?s)a.b", 0, 3);
+    assert_match("a\nb", "(?is)A.B", 0, 3);
+    assert_match("xAy", "x(?i:a|b)y", 0, 3);
+    assert_not_match("xAy", "x(?:a|b)y");
+}
+
+#[test]
+fn test_verbose() {
+    assert_match("12 user=bob", r"(?x) (\d+) \  user = (\w+)", 0, 11);
+    assert_not_match("12user=bob", r"(?x) (\d+) \  user = (\w+)");
+    assert_match(
+        "id#42",
+        "(?x)
+        ^ id    # prefix
+        \\#     # escaped comment char
+        \\d+ $  # number (until end)
+        ",
+        0,
+        5,
+    );
+    assert_match("a b", "(?x: a) b", 0, 3);
+    assert_not_match("ab", "(?x: a) b");
+    assert_match("a b", "a[ ]b", 0, 3);
+}
+
+#[test]
+fn test_unicode_property() {
+    assert_is_match("Ελλάδα", r"\p{Greek}");
+    assert_is_match("Ελλάδα", r"^\p{Greek}+$");
+    assert_not_match("hello", r"\p{Greek}");
+    assert_match("hello world", r"\p{sc=Latin}+", 0, 5);
+    assert_match("hello world", r"\p{White_Space}", 5, 6);
+    assert_match("abc1", r"\p{Nd}", 3, 4);
+    assert_match("ab1", r"\pN", 2, 3);
+    assert_match("ab1", r"\PL", 2, 3);
+    assert_not_match("abc", r"\P{Letter}");
+    assert_match("ab1", r"[\p{Lu}123]", 2, 3);
+    assert_match("abC", r"[\p{Lu}123]", 2, 3);
+    assert_match("ab_c-", r"[^\p{L}_]", 4, 5);
+    assert_match("ab c", r"[\P{L}]", 2, 3);
+    assert_is_match("日本", r"\p{Han}");
+}
+
+#[test]
+fn test_anchors() {
+    assert_match("cat", "(^cat|dog$)", 0, 3);
+    assert_match("a dog", "(^cat|dog$)", 2, 5);
+    assert_not_match("a cat", "(^cat|dog$)");
+    assert_not_match("dogs", "(^cat|dog$)");
+    assert_match("", "^$", 0, 0);
+    assert_match("abc", "$", 3, 3);
+    assert_not_match("ab\ncd", "^cd$");
+    assert_match("ab\ncd", "(?m)b$\n^c", 1, 4);
+    assert_match("ab\ncd", "(?m)(b$)", 1, 2);
+    assert_not_match("ab\ncd", r"(?m)\Acd");
+    assert_match("ab\ncd", r"(?m)\Aab", 0, 2);
+    assert_not_match("ab\n", r"ab\z");
+    assert_match("ab\n", r"ab\Z", 0, 2);
+    assert_match("ab", r"ab\Z", 0, 2);
+    assert_not_match("ab\n\n", r"ab\Z");
+}
+
+#[test]
+fn test_utf8_spans() {
+    // Spans are byte offsets in the input.
+    assert_match("un café noir", "café", 3, 8);
+    assert_match("un café noir", r"caf. (\w+)", 3, 13);
+    assert_match("école élève", r"(é\w+) élève", 0, 14);
+    assert_match("àà-àà", r"(\w+)-\1", 0, 9);
+    assert_match("日本語のテキスト", "テ.スト", 12, 24);
+    assert_match("日本語のテキスト", "[語の]+", 6, 12);
+    assert_match("東京 and 大阪", r"(?<=and )\w+", 11, 17);
+    assert_match("love ❤️ 🦀 crab", "🦀", 12, 16);
+    assert_match("love ❤️ 🦀 crab", r"(🦀|🐍) \w+$", 12, 21);
+    assert_not_match("🦀🦀", r"^.$");
+    assert_match("🦀🦀", r"^..$", 0, 8);
+    assert_not_match("naïve", "nai");
+}
+
+#[test]
+fn test_match_bytes() {
+    let find_bytes = |input: &[u8], pattern: &str| re_parse(pattern).unwrap().find_bytes(input);
+
+    assert_eq!(find_bytes(b"caf\xe9 au lait", r"\w+"), Some((0, 3)));
+    assert_eq!(find_bytes(b"caf\xe9 au lait", r"caf."), None);
+    assert_eq!(find_bytes(b"caf\xe9 au lait", r"(?-u)caf."), Some((0, 4)));
+    assert_eq!(find_bytes(b"caf\xe9 au lait", r"(?-u)\xe9 "), Some((3, 5)));
+    assert_eq!(
+        find_bytes(b"caf\xe9 au lait", r"(?-u)[^\x00 ]+"),
+        Some((0, 4))
+    );
+    assert_eq!(find_bytes(b"\xff\xfe 42", r"\d+$"), Some((3, 5)));
+    assert_eq!(find_bytes(b"\xff\xfe 42", r"^..."), None);
+    assert_eq!(find_bytes(b"\xff\xfe 42", r"(?-u)^..."), Some((0, 3)));
+    assert_eq!(find_bytes(b"\xff\xfe 42", r"(?-u:\w)"), Some((3, 4)));
+    assert_eq!(find_bytes("é".as_bytes(), r"\xe9"), Some((0, 2)));
+    assert_eq!(find_bytes(b"\xc3\xa9", r"(?-u)\xa9"), Some((1, 2)));
+    assert_eq!(find_bytes(b"a\nb", r"(?-u)a.b"), None);
+    assert_eq!(find_bytes(b"a\nb", r"(?s-u)a.b"), Some((0, 3)));
+    assert_eq!(find_bytes(b"ABC", r"(?i-u)[b]c"), Some((1, 3)));
+    assert!(re_parse(r"\d").unwrap().is_match_bytes(b"\x80\x801"));
+    assert!(!re_parse(r"\d").unwrap().is_match_bytes(b"\x80\x80"));
+
+    let find_bytes_at = |input: &[u8], pattern: &str, start: usize| {
+        re_parse(pattern).unwrap().find_bytes_at(input, start)
+    };
+    assert_eq!(find_bytes_at(b"abab", "ab", 1), Some((2, 4)));
+    assert_eq!(find_bytes_at(b"aa", "^a", 1), None);
+    assert_eq!(find_bytes_at(b"ab", "(?<=a)b", 1), Some((1, 2)));
+}
+
+#[test]
+fn test_pike_vm() {
+    // Would take exponential time with a backtracker.
+    let input = "a".repeat(64);
+    assert_not_match(&input, "(a|a)+b");
+    assert_not_match(&input, "(a?)+b");
+    assert_match(&format!("{input}b"), "(a|a)+b", 0, 65);
+
+    // Empty loops terminate.
+    assert_match("bbb", "(a?)+", 0, 0);
+    assert_match("aab", "(a?)+b", 0, 3);
+
+    // Leftmost-first, not leftmost-longest.
+    assert_match("ab", "(a|ab)", 0, 1);
+    assert_match("ab", "(ab|a)", 0, 2);
+    assert_match("xaab", "a?a", 1, 3);
+
+    // Back references still fall back to backtracking.
+    assert_match("xabab", r"(ab)\1", 1, 5);
+}
+
+#[test]
+fn test_line_matcher() {
+    let cases: &[(&str, &[&[u8]])] = &[
+        ("ab+c", &[b"xabbbc", b"abx", b"", b"ac"]),
+        ("^ab", &[b"abc", b"cab", b""]),
+        ("ab$", &[b"cab", b"abc", b"ab\n"]),
+        ("(?m)^b$", &[b"a\nb\nc", b"a\nbc", b"b"]),
+        (r"\AB", &[b"B", b"AB"]),
+        (r"a\Z", &[b"a\n", b"a\nb"]),
+        ("caf.$", &["café".as_bytes(), b"caf\xe9", b"cafe"]),
+        (
+            "日本",
+            &["日本語".as_bytes(), "日語".as_bytes(), b"\xe6\x97"],
+        ),
+        (r"(?-u)\xe9", &[b"caf\xe9", "é".as_bytes()]),
+        (r"(?-u)^.\xa9", &["é".as_bytes()]),
+        (r"[ab]+a[ab]?$", &[b"bba", b"bbab", b"bbabb"]),
+        (r"(\w+)-\1", &[b"ab-ab", b"ab-cd"]),
+        ("(?<=a)b", &[b"ab", b"cb"]),
+    ];
+
+    for (pattern, inputs) in cases {
+        let re = re_parse(pattern).unwrap();
+        let mut matcher = re.line_matcher();
+        for input in *inputs {
+            assert_eq!(
+                matcher.is_match(input),
+                re.find_bytes(input).is_some(),
+                "{pattern} on {input:?}"
+            );
+        }
+    }
+}
+
+#[test]
+fn test_line_matcher_cache_thrashing() {
+    // Needs one DFA state for each combination of the last 14 letters.
+    let re = re_parse(&format!("a{}$", "[ab]".repeat(13))).unwrap();
+    let mut matcher = re.line_matcher();
+
+    let mut seed = 42_u32;
+    for _ in 0..20 {
+        let line: Vec<u8> = (0..2000)
+            .map(|_| {
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                if seed >> 16 & 1 == 0 {
+                    b'a'
+                } else {
+                    b'b'
+                }
+            })
+            .collect();
+
+        assert_eq!(matcher.is_match(&line), re.find_bytes(&line).is_some());
+        assert!(matcher.is_match(&[&line[..], b"abbbbbbbbbbbbb"].concat()));
+        assert!(!matcher.is_match(&[&line[..], b"bbbbbbbbbbbbbb"].concat()));
+    }
+}
+
+#[test]
+fn test_literal_prefilter() {
+    assert_match("INFO 1 ERROR 42 ERROR 7", r"ERROR \d+", 7, 15);
+    assert_match("ERRORERROR 1", r"ERROR \d+", 5, 12);
+    assert_not_match("ERROR x ERROR", r"ERROR \d+");
+    assert_match("xxabcabcd", "abc+d", 5, 9);
+    assert_match("a-b-c-d", r"\w-d", 4, 7);
+    assert_match("a b c d", "(b) c", 2, 5);
+    assert_match("x12y", r"^x\d+y$", 0, 4);
+    assert_match("line\nfoo", "(?m)^foo", 5, 8);
+    assert_match("été à la plage", "à la", 6, 11);
+    assert_match("abab", r"(ab)\1", 0, 4);
+    assert_not_match("ab ac", r"(a)b\1c");
+
+    // Needle found at every offset of word-at-a-time scanning.
+    for idx in 0..40 {
+        let input = format!("{}needle{}", ".".repeat(idx), ".".repeat(40 - idx));
+        assert_match(&input, "ne+dle", idx, idx + 6);
+        assert!(!re_parse("needles")
+            .unwrap()
+            .is_match_bytes(input.as_bytes()));
+    }
+}
+
+#[test]
+fn test_fixed_string() {
+    let find =
+        |needle: &str, haystack: &str| FixedString::new(needle, false).find(haystack.as_bytes());
+    assert_eq!(find("a.b[c]", "xa.b[c]"), Some((1, 7)));
+    assert_eq!(find("a.b[c]", "axb[c]"), None);
+    assert_eq!(find("abcab", "abcadabcab"), Some((5, 10)));
+    assert_eq!(find("", "abc"), Some((0, 0)));
+    assert_eq!(find("abc", "ab"), None);
+    assert_eq!(find("élève", "un élève"), Some((3, 10)));
+    assert_eq!(find("Foo", "foo"), None);
+
+    let find_ignore_case =
+        |needle: &str, haystack: &str| FixedString::new(needle, true).find(haystack.as_bytes());
+    assert_eq!(find_ignore_case("Foo", "a FOO"), Some((2, 5)));
+    assert_eq!(find_ignore_case("[X]", "a [x]"), Some((2, 5)));
+    assert_eq!(find_ignore_case("été", "L'ÉTÉ"), Some((2, 7)));
+    assert_eq!(find_ignore_case("été", "L'ETE"), None);
+
+    let find_word = |needle: &str, haystack: &str| {
+        FixedString::new(needle, false)
+            .whole_word(true)
+            .find(haystack.as_bytes())
+    };
+    assert_eq!(find_word("foo", "foobar foo"), Some((7, 10)));
+    assert_eq!(find_word("foo", "foobar"), None);
+    assert_eq!(find_word("foo", "(foo)"), Some((1, 4)));
+    assert_eq!(find_word("foo", "éfoo foo"), Some((6, 9)));
+    // Same word chars as `\w`.
+    assert_eq!(find_word("foo", "foo_bar _foo foo"), Some((13, 16)));
+    assert!(!FixedString::new("b", false)
+        .whole_word(true)
+        .is_match(b"abc"));
+}
+
+#[test]
+fn test_aho_corasick() {
+    let automaton = AhoCorasick::new(["he", "she", "his", "hers"], false);
+    assert_eq!(automaton.find(b"ushers"), Some((1, (1, 4))));
+    assert_eq!(automaton.find_at(b"ushers", 2), Some((3, (2, 6))));
+    assert_eq!(automaton.find(b"this"), Some((2, (1, 4))));
+    assert_eq!(automaton.find(b"hi"), None);
+    assert!(!automaton.is_match(b"HERS"));
+
+    // Leftmost match wins even if a shorter one ends first.
+    let automaton = AhoCorasick::new(["abcd", "bc"], false);
+    assert_eq!(automaton.find(b"xabcd"), Some((0, (1, 5))));
+    let automaton = AhoCorasick::new(["ab", "abc", "b"], false);
+    assert_eq!(automaton.find(b"abc"), Some((1, (0, 3))));
+
+    let automaton = AhoCorasick::new(["ID-12", "ID-123"], true);
+    assert_eq!(automaton.find(b"id-123"), Some((1, (0, 6))));
+
+    let automaton = AhoCorasick::new(["ID-12", "ID-123"], false).whole_word(true);
+    assert_eq!(automaton.find(b"ID-124 ID-12"), Some((0, (7, 12))));
+    assert_eq!(automaton.find(b"ID-1234"), None);
+
+    let automaton = AhoCorasick::new(["", "ab"], false);
+    assert_eq!(automaton.find(b"xab"), Some((0, (0, 0))));
+    assert_eq!(automaton.find(b"ab"), Some((1, (0, 2))));
+
+    let ids: Vec<String> = (0..2000).map(|id| format!("user-{id}")).collect();
+    let automaton = AhoCorasick::new(&ids, false).whole_word(true);
+    assert_eq!(automaton.find(b"login user-1999 ok"), Some((1999, (6, 15))));
+    assert_eq!(automaton.find(b"login user-20000"), None);
+}
+
+#[test]
+fn test_regex_set() {
+    let set = RegexSet::new([
+        r"ERROR \d+",
+        r"^\w+:",
+        "timeout",
+        r"(\w+) \1",
+        "(?i)disk (full|failure)",
+        r"\d+$",
+    ])
+    .unwrap();
+    assert_eq!(set.len(), 6);
+
+    assert_eq!(set.matches("ERROR 42 timeout"), vec![0, 2]);
+    assert_eq!(set.matches("db: Disk FULL after 3"), vec![1, 4, 5]);
+    assert_eq!(set.matches("retry retry 12"), vec![3, 5]);
+    assert_eq!(set.matches("all good"), Vec::<usize>::new());
+    assert!(set.is_match("x db:timeout"));
+    assert!(!set.is_match("x db:"));
+    assert_eq!(set.matches_bytes(b"\xff ERROR 7"), vec![0, 5]);
+
+    // Empty sets and sets only made of backtracked regexes.
+    assert!(RegexSet::new(Vec::<&str>::new()).unwrap().is_empty());
+    let set = RegexSet::new([r"(a)\1", "(?<=b)c"]).unwrap();
+    assert_eq!(set.matches("aa bc"), vec![0, 1]);
+
+    assert_eq!(
+        RegexSet::new(["a", "("]).unwrap_err(),
+        ParseError::new("(", ParseErrorKind::UnclosedGroup, 1..1).into()
+    );
+
+    // Single pass over many rules.
+    let rules: Vec<String> = (0..200).map(|idx| format!("rule{idx}[xy]+z")).collect();
+    let set = RegexSet::new(&rules).unwrap();
+    assert_eq!(set.matches("rule7xz rule150yyz rule99z"), vec![7, 150]);
+}
+
+#[test]
+fn test_regexp_compile_once() {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<Regexp>();
+
+    let re = Regexp::new(r"(\w+)@(\w+)").unwrap();
+    assert!(re.is_match("mail me at bob@example"));
+    assert_eq!(re.find("mail me at bob@example"), Some((11, 22)));
+    assert_eq!(re.find("no mail"), None);
+
+    // Clones share the compiled program and can be used from many threads.
+    let handles: Vec<_> = (0..4)
+        .map(|idx| {
+            let re = re.clone();
+            std::thread::spawn(move || {
+                (0..100).all(|_| re.is_match(&format!("user{idx}@host")) && !re.is_match("@"))
+            })
+        })
+        .collect();
+    assert!(handles.into_iter().all(|handle| handle.join().unwrap()));
+
+    let unclosed = |pattern| ParseError::new(pattern, ParseErrorKind::UnclosedGroup, 2..2).into();
+    assert_eq!(Regexp::new("(a").map(|_| ()), Err(unclosed("(a")));
+    assert_eq!(match_pattern("abc", "(b"), Err(unclosed("(b")));
+    assert_eq!(match_pattern("abc", "b+"), Ok(Some((1, 2))));
+    assert_eq!(Regexp::new("ab+").unwrap().patterns().len(), 2);
+}
+
+#[test]
+fn test_match_limit() {
+    // Back reference forces backtracking, which is exponential when no class char follows.
+    let input = "a".repeat(30);
+    let re = Regexp::new(r"(a+)+\1[bc]")
+        .unwrap()
+        .with_match_limit(10_000);
+    assert_eq!(re.try_find(&input), Err(GrepError::MatchLimitExceeded));
+    assert_eq!(re.find(&input), None);
+    assert!(!re.is_match(&input));
+    assert_eq!(re.try_find("aab"), Ok(Some((0, 3))));
+
+    let mut matcher = re.line_matcher();
+    assert_eq!(
+        matcher.try_is_match(input.as_bytes()),
+        Err(GrepError::MatchLimitExceeded)
+    );
+    assert_eq!(matcher.try_is_match(b"xaaab"), Ok(true));
+
+    // Bounded backtracker on short inputs finds same matches as Pike VM on long ones.
+    let long = "x".repeat(100_000);
+    for (pattern, input, expected) in [
+        ("(a|ab)(c|bcd)(d?)", "abcd", (0, 4)),
+        ("(a+)+b", "aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaab", (0, 32)),
+        (r"o\d+$", "a foo123", (4, 8)),
+    ] {
+        let re = Regexp::new(pattern).unwrap();
+        assert_eq!(re.find(input), Some(expected));
+        let padded = format!("{long}{input}");
+        let offset = long.len();
+        assert_eq!(
+            re.find(&padded),
+            Some((expected.0 + offset, expected.1 + offset))
+        );
+    }
+    assert_eq!(Regexp::new("(a+)+b").unwrap().find(&"a".repeat(40)), None);
+}
+
+#[test]
+fn test_deadline_and_cancellation() {
+    use std::time::{Duration, Instant};
+
+    // Past deadline stops searches of every engine: bounded backtracker on short input,
+    // Pike VM on long input, lazy DFA and backtracking of back references.
+    let past = Instant::now();
+    let long = "x".repeat(100_000);
+    let re = Regexp::new(r"\d+").unwrap().with_deadline(past);
+    assert_eq!(re.try_find("abc"), Err(GrepError::DeadlineExceeded));
+    assert_eq!(re.try_find(&long), Err(GrepError::DeadlineExceeded));
+    assert_eq!(
+        re.try_is_match_bytes(long.as_bytes()),
+        Err(GrepError::DeadlineExceeded)
+    );
+    assert_eq!(re.find("abc1"), None);
+    let re = Regexp::new(r"(a+)+\1[bc]").unwrap().with_deadline(past);
+    assert_eq!(
+        re.try_find(&"a".repeat(30)),
+        Err(GrepError::DeadlineExceeded)
+    );
+
+    let re = Regexp::new(r"\d+")
+        .unwrap()
+        .with_deadline(Instant::now() + Duration::from_secs(3600));
+    assert_eq!(re.try_find("abc12"), Ok(Some((3, 5))));
+
+    let token = CancelToken::new();
+    let re = Regexp::new(r"\d+")
+        .unwrap()
+        .with_cancel_token(token.clone());
+    assert_eq!(re.try_find("abc12"), Ok(Some((3, 5))));
+    token.cancel();
+    assert!(token.is_cancelled());
+    assert_eq!(re.try_find("abc12"), Err(GrepError::Cancelled));
+    assert_eq!(
+        re.line_matcher().try_is_match(b"abc12"),
+        Err(GrepError::Cancelled)
+    );
+
+    // Catastrophic search is aborted from another thread.
+    let token = CancelToken::new();
+    let re = Regexp::new(r"(a+)+\1[bc]")
+        .unwrap()
+        .with_match_limit(usize::MAX)
+        .with_cancel_token(token.clone());
+    let handle = std::thread::spawn(move || re.try_find(&"a".repeat(40)));
+    std::thread::sleep(Duration::from_millis(50));
+    token.cancel();
+    assert_eq!(handle.join().unwrap(), Err(GrepError::Cancelled));
+}
+
+#[test]
+fn test_long_line_backtracking() {
+    // Back reference forces backtracking, which must not recurse once per char.
+    let line = format!("aa{}xb", "b".repeat(1_000_000));
+    assert_match(&line, r"(a)\1.+x", 0, line.len() - 1);
+    assert_not_match(&line, r"(a)\1.+x$");
+    assert_match(&line, r"(?<=a)(b)\1+", 2, line.len() - 2);
+    assert_match(&line, r"(a|b)\1(?!a)b+", 0, line.len() - 2);
+}
+
+#[test]
+fn test_leftmost_longest() {
+    let first = Regexp::new("(a|ab)(c|bcd)?").unwrap();
+    let longest = first.clone().with_match_kind(MatchKind::LeftmostLongest);
+    assert_eq!(first.find("xabc"), Some((1, 2)));
+    assert_eq!(longest.find("xabc"), Some((1, 4)));
+    assert_eq!(longest.find("xabcd"), Some((1, 5)));
+    assert_eq!(longest.find("xab"), Some((1, 3)));
+    assert_eq!(longest.find("xy"), None);
+
+    // Leftmost match wins over a longer one starting later.
+    let longest = Regexp::new("(b|abc|bcde)")
+        .unwrap()
+        .with_match_kind(MatchKind::LeftmostLongest);
+    assert_eq!(longest.find("abcde"), Some((0, 3)));
+    assert_eq!(longest.find("xbcde"), Some((1, 5)));
+
+    // Long inputs are searched by the Pike VM, backtracked patterns exhaustively.
+    let input = format!("{}ab", "x".repeat(100_000));
+    let longest = Regexp::new("(a|ab)")
+        .unwrap()
+        .with_match_kind(MatchKind::LeftmostLongest);
+    assert_eq!(longest.find(&input), Some((100_000, 100_002)));
+    let longest = Regexp::new(r"(a|ab)\1?")
+        .unwrap()
+        .with_match_kind(MatchKind::LeftmostLongest);
+    assert_eq!(longest.find("abab"), Some((0, 4)));
+    assert_eq!(
+        Regexp::new(r"(a|ab)\1?").unwrap().find("abab"),
+        Some((0, 1))
+    );
+    // Exhaustive search does not explore the same state twice, even on short lines.
+    let longest = Regexp::new(r"(b)?(?:a?){0,3}*\1")
+        .unwrap()
+        .with_match_kind(MatchKind::LeftmostLongest);
+    assert_eq!(longest.try_find("aaaaaaaa"), Ok(None));
+    assert_eq!(longest.try_find(&"a".repeat(16)), Ok(None));
+    let longest = Regexp::new(r"(a)(?:a?){0,3}*\1")
+        .unwrap()
+        .with_match_kind(MatchKind::LeftmostLongest);
+    assert_eq!(longest.try_find("aaaaaaab"), Ok(Some((0, 7))));
+}
+
+#[test]
+fn test_match_repetition() {
+    assert_match("xac", "ab*c", 1, 3);
+    assert_match("xabbbc", "ab*c", 1, 6);
+    assert_match("aaaa", "a{2}", 0, 2);
+    assert_match("aaaa", "a{2,3}", 0, 3);
+    assert_match("aaaa", "a{2,}", 0, 4);
+    assert_match("ab", "a{,2}b", 0, 2);
+    assert_not_match("ab", "a{2}");
+    assert_match("abcabc", "(abc){2}", 0, 6);
+    assert_match("a.b(c)", r"\.b\(c\)", 1, 6);
+    assert_match("1+1=2", r"1\+1", 0, 3);
+    assert_match("x{y}", "x{y}", 0, 4);
+
+    let re = re_parse_basic(r"\(ab\)\{2\}\|x+", Flags::default()).unwrap();
+    assert_eq!(re.find("zabab"), Some((1, 5)));
+    assert_eq!(re.find("x+"), Some((0, 2)));
+    assert_eq!(re.find("xx"), None);
+
+    // GNU word anchors.
+    let re = re_parse_basic(r"\<foo\>", Flags::default()).unwrap();
+    assert_eq!(re.find("foo1 foobar (foo)"), Some((13, 16)));
+    assert_eq!(re.find("foo>bar"), Some((0, 3)));
+    assert_eq!(re.find("foobar"), None);
+}
+
+#[test]
+fn test_empty_iterations() {
+    // Iterations after the first one must consume input, whether the pattern is compiled or
+    // backtracked, which an always true lookbehind forces.
+    let inputs: Vec<String> = (0..5)
+        .flat_map(|len| {
+            (0..1 << len).map(move |bits: usize| {
+                (0..len)
+                    .map(|idx| if bits >> idx & 1 == 0 { 'a' } else { 'b' })
+                    .collect()
+            })
+        })
+        .collect();
+    for pattern in [
+        "(.*?[^a]?|a[ab])+",
+        "(a?)+b",
+        "(a*)*",
+        "(a|b?)+?",
+        "(b*|a)+a",
+        "(a??b??)+",
+        "((a?)*b?)+",
+        "(a|b{0})+",
+        "(a*?)+?b",
+        "(a?b?)*?$",
+        "(?:a?|ab)+b",
+    ] {
+        for kind in [MatchKind::LeftmostFirst, MatchKind::LeftmostLongest] {
+            let compiled = Regexp::new(pattern).unwrap().with_match_kind(kind);
+            let backtracked = Regexp::new(&format!(r"(?<!\x00){pattern}"))
+                .unwrap()
+                .with_match_kind(kind);
+            for input in &inputs {
+                assert_eq!(
+                    compiled.find(input),
+                    backtracked.find(input),
+                    "{pattern} {kind:?} {input}"
+                );
+            }
+        }
+    }
+    assert_eq!(
+        Regexp::new(r"(?<!\x00)(.*?[^a]?|a[ab])+")
+            .unwrap()
+            .find("baa"),
+        Some((0, 3))
+    );
+}
+
+#[test]
+fn test_lazy_quantifiers() {
+    assert_match("<a><b>", "<.+?>", 0, 3);
+    assert_match("<a><b>", "<.*?>", 0, 3);
+    assert_match("aaa", "a{2,3}?", 0, 2);
+    assert_match("aaa", "a??", 0, 0);
+    assert_match("aab", "(a+?)b", 0, 3);
+    assert_match("abab", "(a|b)+?b", 0, 2);
+
+    // Back references and lookaround are backtracked.
+    assert_match("xaabaab", r"(a+?b)\1", 1, 7);
+    assert_match("<a><b>", "(?<=x?)<(.+?)>", 0, 3);
+    assert_match("aab", "(?=a)(a+?)b", 0, 3);
+    assert_match("ababab", "(?=a)((ab)+?)(ab)", 0, 4);
+
+    // Leftmost-longest matching ignores laziness.
+    let re = re_parse("<.+?>")
+        .unwrap()
+        .with_match_kind(MatchKind::LeftmostLongest);
+    assert_eq!(re.find("<a><b>"), Some((0, 6)));
+}
+
+#[test]
+fn test_match_reset_start() {
+    assert_match("foo=bar", r"foo=\Kbar", 4, 7);
+    assert_match("foo=bar", r"(foo=\K)bar", 4, 7);
+    assert_match("aab", r"a\K(a|b)+", 1, 3);
+    assert_match("abc", r"a\K", 1, 1);
+    assert_match("abc", r"(?=ab\K)a", 0, 1);
+    assert_not_match("foo=baz", r"foo=\Kbar");
+
+    let re = re_parse(r"a\K(b|bc)")
+        .unwrap()
+        .with_match_kind(MatchKind::LeftmostLongest);
+    assert_eq!(re.find("xabc"), Some((2, 4)));
+}
+
+#[test]
+fn test_match_perl() {
+    let find = |pattern: &str, input: &str| {
+        re_parse_perl(pattern, Flags::default())
+            .unwrap()
+            .find(input)
+    };
+
+    assert_eq!(find(r"(?<key>\w+)=\k<key>", "a=b c=c"), Some((4, 7)));
+    assert_eq!(find(r"\h+\K\S+", "x \u{a0}\tyz"), Some((5, 7)));
+    assert_eq!(find(r"\R", "a\r\nb"), Some((1, 3)));
+    assert_eq!(find(r"\Q1+1\E=2", "1+1=2"), Some((0, 5)));
+    assert_eq!(find(r"\bcat\b", "concat cat"), Some((7, 10)));
+    assert_eq!(find(r"\Bcat", "cat concat"), Some((7, 10)));
+    assert_eq!(find(r"[a-c]+", "xbcay"), Some((1, 4)));
+    assert_eq!(find(r"\w+", "-snake_case-"), Some((1, 11)));
+    assert_eq!(find("a|bc", "xbc"), Some((1, 3)));
+    assert_eq!(
+        find(r"(a)(b)(c)(d)(e)(f)(g)(h)(\w)\9", "abcdefghii"),
+        Some((0, 10))
+    );
+    assert_eq!(find("a.b", "a\rb"), Some((0, 3)));
+    assert_eq!(find("a.b", "a\nb"), None);
+    assert_eq!(find("(?x) a b # [comment", "ab"), Some((0, 2)));
 }
@@ -1,353 +1,1427 @@
+use std::{iter, ops::Range};
+
 use grep_starter_rust::*;
 
 #[test]
 fn test_debug() {
     assert_eq!(format!("{:?}", Pattern::Chars), "Chars");
     assert_eq!(
-        format!(
-            "{:?}",
-            Regexp {
-                patterns: vec![Pattern::Chars]
-            }
-        ),
+        format!("{:?}", Regexp::from(vec![Pattern::Chars])),
         "Regexp { patterns: [Chars] }"
     );
 }
 
+#[test]
+fn test_display() {
+    for (pattern, printed) in [
+        (r"a\.b+?\$", r"a\.b+?\$"),
+        ("a|(b|c)*", "(?:a|(b|c)*)"),
+        ("a{2,3}", "(?:aaa?)"),
+        ("a*?b+{0,1}?c{1,}??", "a*?b+{0,1}?c+??"),
+        (
+            r"[^a\x5D-][\pL\P{Greek}]",
+            r"[^a\x5D-][\p{Letter}\P{Greek}]",
+        ),
+        (r"(?m)^a(?s:.)$\A", r"(?m)^a(?:(?s).)$(?-m)^"),
+        (r"(?-u)\xE9\x41.", r"(?-u)\xE9A[^\x0A\x0D]"),
+        (r"(?<=a)(?!b)\K\1\Z", r"(?<=a)(?!b)\K\1\Z"),
+        ("x{0}(y{0}|z)", "a{0}(a{0}|z)"),
+        ("(a){2}(b){1,3}?(c){2,}", "(a){2}(b){1,3}?(c){2,}"),
+        (r"(?:(a)x){0,2}\1", r"(?:(a)x){0,2}\1"),
+        ("(a){0}((b){0}c)(d)x{0}", "(a){0}((a){0}c)(d)a{0}"),
+        ("(a){2}{0,1}(b){1,2}{0,1}?", "(a){2}{0,1}(b){1,2}{0,1}?"),
+        ("((a)(b)){0}(c)", "(?:(a)(a)(a)){0}(c)"),
+        (
+            r"(?i)s\p{Lu}[1\PL]x",
+            r"[sSſ](?i)\p{Uppercase_Letter}[1\P{Letter}](?-i)[xX]",
+        ),
+    ] {
+        let re = re_parse(pattern).unwrap();
+        assert_eq!(re.to_string(), printed, "{pattern}");
+        assert_eq!(re_parse(printed).unwrap(), re, "{pattern}");
+    }
+    assert_eq!(Pattern::Literal('(').to_string(), r"\(");
+}
+
 #[test]
 fn test_parse_pattern() {
     assert_eq!(
         re_parse(r"hello").unwrap(),
-        Regexp {
-            patterns: vec![
-                Pattern::Literal('h'),
-                Pattern::Literal('e'),
-                Pattern::Literal('l'),
-                Pattern::Literal('l'),
-                Pattern::Literal('o'),
-            ],
-        }
+        Regexp::from(vec![
+            Pattern::Literal('h'),
+            Pattern::Literal('e'),
+            Pattern::Literal('l'),
+            Pattern::Literal('l'),
+            Pattern::Literal('o'),
+        ])
     );
 
-    assert_eq!(
-        re_parse(r"\d").unwrap(),
-        Regexp {
-            patterns: vec![Pattern::Digit],
-        }
-    );
+    assert_eq!(re_parse(r"\d").unwrap(), Regexp::from(vec![Pattern::Digit]));
 
-    assert_eq!(
-        re_parse(r"\w").unwrap(),
-        Regexp {
-            patterns: vec![Pattern::Chars],
-        }
-    );
+    assert_eq!(re_parse(r"\w").unwrap(), Regexp::from(vec![Pattern::Chars]));
 
     assert_eq!(
         re_parse(r"[abc]").unwrap(),
-        Regexp {
-            patterns: vec![Pattern::PositiveCharGroup(vec!['a', 'b', 'c'])],
-        }
+        Regexp::from(vec![Pattern::PositiveCharGroup(vec!['a', 'b', 'c'])])
     );
 
     assert_eq!(
         re_parse(r"[^defg]").unwrap(),
-        Regexp {
-            patterns: vec![Pattern::NegativeCharGroup(vec!['d', 'e', 'f', 'g'])],
-        }
+        Regexp::from(vec![Pattern::NegativeCharGroup(vec!['d', 'e', 'f', 'g'])])
     );
 
     assert_eq!(
         re_parse(r"\d apple").unwrap(),
-        Regexp {
-            patterns: vec![
-                Pattern::Digit,
-                Pattern::Literal(' '),
-                Pattern::Literal('a'),
-                Pattern::Literal('p'),
-                Pattern::Literal('p'),
-                Pattern::Literal('l'),
-                Pattern::Literal('e'),
-            ],
-        }
+        Regexp::from(vec![
+            Pattern::Digit,
+            Pattern::Literal(' '),
+            Pattern::Literal('a'),
+            Pattern::Literal('p'),
+            Pattern::Literal('p'),
+            Pattern::Literal('l'),
+            Pattern::Literal('e'),
+        ])
     );
 
     assert_eq!(
         re_parse(r"\d \d ap[plx]le").unwrap(),
-        Regexp {
-            patterns: vec![
-                Pattern::Digit,
-                Pattern::Literal(' '),
-                Pattern::Digit,
-                Pattern::Literal(' '),
-                Pattern::Literal('a'),
-                Pattern::Literal('p'),
-                Pattern::PositiveCharGroup(vec!['p', 'l', 'x']),
-                Pattern::Literal('l'),
-                Pattern::Literal('e'),
-            ],
-        }
+        Regexp::from(vec![
+            Pattern::Digit,
+            Pattern::Literal(' '),
+            Pattern::Digit,
+            Pattern::Literal(' '),
+            Pattern::Literal('a'),
+            Pattern::Literal('p'),
+            Pattern::PositiveCharGroup(vec!['p', 'l', 'x']),
+            Pattern::Literal('l'),
+            Pattern::Literal('e'),
+        ])
     );
 
     assert_eq!(
         re_parse(r"d^d").unwrap(),
-        Regexp {
-            patterns: vec![
-                Pattern::Literal('d'),
-                Pattern::Literal('^'),
-                Pattern::Literal('d'),
-            ],
-        }
+        Regexp::from(vec![
+            Pattern::Literal('d'),
+            Pattern::Start,
+            Pattern::Literal('d')
+        ])
     );
 
     assert_eq!(
         re_parse(r"^\dd").unwrap(),
-        Regexp {
-            patterns: vec![Pattern::Start, Pattern::Digit, Pattern::Literal('d')],
-        }
+        Regexp::from(vec![Pattern::Start, Pattern::Digit, Pattern::Literal('d')])
     );
 
     assert_eq!(
         re_parse(r"d$d").unwrap(),
-        Regexp {
-            patterns: vec![
-                Pattern::Literal('d'),
-                Pattern::Literal('$'),
-                Pattern::Literal('d'),
-            ],
-        }
+        Regexp::from(vec![
+            Pattern::Literal('d'),
+            Pattern::End,
+            Pattern::Literal('d')
+        ])
     );
 
     assert_eq!(
         re_parse(r"\dd$").unwrap(),
-        Regexp {
-            patterns: vec![Pattern::Digit, Pattern::Literal('d'), Pattern::End],
-        }
+        Regexp::from(vec![Pattern::Digit, Pattern::Literal('d'), Pattern::End])
     );
 
     assert_eq!(
         re_parse(r"\w+").unwrap(),
-        Regexp {
-            patterns: vec![Pattern::OneOrMore(Box::new(Pattern::Chars))],
-        }
+        Regexp::from(vec![Pattern::OneOrMore(Box::new(Pattern::Chars))])
     );
 
     assert_eq!(
         re_parse(r"xx+x").unwrap(),
-        Regexp {
-            patterns: vec![
-                Pattern::Literal('x'),
-                Pattern::OneOrMore(Box::new(Pattern::Literal('x'))),
-                Pattern::Literal('x')
-            ],
-        }
+        Regexp::from(vec![
+            Pattern::Literal('x'),
+            Pattern::OneOrMore(Box::new(Pattern::Literal('x'))),
+            Pattern::Literal('x')
+        ])
     );
 
     assert_eq!(
         re_parse(r"^x[aze]+").unwrap(),
-        Regexp {
-            patterns: vec![
-                Pattern::Start,
-                Pattern::Literal('x'),
-                Pattern::OneOrMore(Box::new(Pattern::PositiveCharGroup(vec!['a', 'z', 'e'])))
-            ],
-        }
+        Regexp::from(vec![
+            Pattern::Start,
+            Pattern::Literal('x'),
+            Pattern::OneOrMore(Box::new(Pattern::PositiveCharGroup(vec!['a', 'z', 'e'])))
+        ])
     );
 
     assert_eq!(
         re_parse(r"(cat|dog)").unwrap(),
-        Regexp {
-            patterns: vec![Pattern::Alternation {
+        Regexp::from(vec![Pattern::Alternation {
+            alternations: vec![
+                vec![
+                    Pattern::Literal('c'),
+                    Pattern::Literal('a'),
+                    Pattern::Literal('t')
+                ],
+                vec![
+                    Pattern::Literal('d'),
+                    Pattern::Literal('o'),
+                    Pattern::Literal('g')
+                ]
+            ],
+            id: 1,
+        }])
+    );
+
+    assert_eq!(
+        re_parse(r"^\d (cat|dog\d+|duc\w)s?$").unwrap(),
+        Regexp::from(vec![
+            Pattern::Start,
+            Pattern::Digit,
+            Pattern::Literal(' '),
+            Pattern::Alternation {
                 alternations: vec![
                     vec![
                         Pattern::Literal('c'),
                         Pattern::Literal('a'),
-                        Pattern::Literal('t')
+                        Pattern::Literal('t'),
                     ],
                     vec![
                         Pattern::Literal('d'),
                         Pattern::Literal('o'),
-                        Pattern::Literal('g')
-                    ]
+                        Pattern::Literal('g'),
+                        Pattern::OneOrMore(Box::new(Pattern::Digit)),
+                    ],
+                    vec![
+                        Pattern::Literal('d'),
+                        Pattern::Literal('u'),
+                        Pattern::Literal('c'),
+                        Pattern::Chars,
+                    ],
                 ],
                 id: 1,
-            }],
-        }
+            },
+            Pattern::ZeroOrOne(Box::new(Pattern::Literal('s'))),
+            Pattern::End,
+        ])
     );
 
     assert_eq!(
-        re_parse(r"^\d (cat|dog\d+|duc\w)s?$").unwrap(),
-        Regexp {
-            patterns: vec![
-                Pattern::Start,
-                Pattern::Digit,
-                Pattern::Literal(' '),
-                Pattern::Alternation {
-                    alternations: vec![
-                        vec![
+        re_parse(r"\1").unwrap(),
+        Regexp::from(vec![Pattern::BackReference(1)])
+    );
+
+    assert_eq!(
+        re_parse(r"('(cat) and \2') is the same as \1").unwrap(),
+        Regexp::from(vec![
+            Pattern::Alternation {
+                alternations: vec![vec![
+                    Pattern::Literal('\''),
+                    Pattern::Alternation {
+                        alternations: vec![vec![
                             Pattern::Literal('c'),
                             Pattern::Literal('a'),
                             Pattern::Literal('t'),
+                        ]],
+                        id: 2,
+                    },
+                    Pattern::Literal(' '),
+                    Pattern::Literal('a'),
+                    Pattern::Literal('n'),
+                    Pattern::Literal('d'),
+                    Pattern::Literal(' '),
+                    Pattern::BackReference(2),
+                    Pattern::Literal('\''),
+                ]],
+                id: 1,
+            },
+            Pattern::Literal(' '),
+            Pattern::Literal('i'),
+            Pattern::Literal('s'),
+            Pattern::Literal(' '),
+            Pattern::Literal('t'),
+            Pattern::Literal('h'),
+            Pattern::Literal('e'),
+            Pattern::Literal(' '),
+            Pattern::Literal('s'),
+            Pattern::Literal('a'),
+            Pattern::Literal('m'),
+            Pattern::Literal('e'),
+            Pattern::Literal(' '),
+            Pattern::Literal('a'),
+            Pattern::Literal('s'),
+            Pattern::Literal(' '),
+            Pattern::BackReference(1),
+        ])
+    );
+
+    assert_eq!(
+        re_parse(r"((abc|def)|ghi)(jkl|mno|(\w+))(pqr)").unwrap(),
+        Regexp::from(vec![
+            Pattern::Alternation {
+                alternations: vec![
+                    vec![Pattern::Alternation {
+                        alternations: vec![
+                            vec![
+                                Pattern::Literal('a'),
+                                Pattern::Literal('b'),
+                                Pattern::Literal('c'),
+                            ],
+                            vec![
+                                Pattern::Literal('d'),
+                                Pattern::Literal('e'),
+                                Pattern::Literal('f'),
+                            ],
                         ],
-                        vec![
-                            Pattern::Literal('d'),
-                            Pattern::Literal('o'),
-                            Pattern::Literal('g'),
-                            Pattern::OneOrMore(Box::new(Pattern::Digit)),
-                        ],
-                        vec![
-                            Pattern::Literal('d'),
-                            Pattern::Literal('u'),
-                            Pattern::Literal('c'),
-                            Pattern::Chars,
-                        ],
+                        id: 2
+                    }],
+                    vec![
+                        Pattern::Literal('g'),
+                        Pattern::Literal('h'),
+                        Pattern::Literal('i'),
                     ],
-                    id: 1,
-                },
-                Pattern::ZeroOrOne(Box::new(Pattern::Literal('s'))),
-                Pattern::End,
+                ],
+                id: 1
+            },
+            Pattern::Alternation {
+                alternations: vec![
+                    vec![
+                        Pattern::Literal('j'),
+                        Pattern::Literal('k'),
+                        Pattern::Literal('l'),
+                    ],
+                    vec![
+                        Pattern::Literal('m'),
+                        Pattern::Literal('n'),
+                        Pattern::Literal('o'),
+                    ],
+                    vec![Pattern::Alternation {
+                        alternations: vec![vec![Pattern::OneOrMore(Box::new(Pattern::Chars))]],
+                        id: 4
+                    }]
+                ],
+                id: 3
+            },
+            Pattern::Alternation {
+                alternations: vec![vec![
+                    Pattern::Literal('p'),
+                    Pattern::Literal('q'),
+                    Pattern::Literal('r'),
+                ]],
+                id: 5
+            },
+        ])
+    );
+}
+
+#[test]
+fn test_parse_look_around() {
+    assert_eq!(
+        re_parse(r"(?<=a|bc)d(?!e)").unwrap(),
+        Regexp::from(vec![
+            Pattern::LookBehind {
+                alternations: vec![
+                    vec![Pattern::Literal('a')],
+                    vec![Pattern::Literal('b'), Pattern::Literal('c')],
+                ],
+                negative: false,
+            },
+            Pattern::Literal('d'),
+            Pattern::LookAhead {
+                alternations: vec![vec![Pattern::Literal('e')]],
+                negative: true,
+            },
+        ])
+    );
+
+    assert_eq!(
+        re_parse(r"(?<!x)").unwrap(),
+        Regexp::from(vec![Pattern::LookBehind {
+            alternations: vec![vec![Pattern::Literal('x')]],
+            negative: true,
+        }])
+    );
+}
+
+#[test]
+fn test_parse_inline_flags() {
+    assert_eq!(
+        re_parse(r"(?i)a1[^b]").unwrap(),
+        Regexp::from(vec![
+            Pattern::PositiveCharGroup(vec!['a', 'A']),
+            Pattern::Literal('1'),
+            Pattern::NegativeCharGroup(vec!['b', 'B']),
+        ])
+    );
+
+    assert_eq!(
+        re_parse(r"(?ms)^.(?-s:.)$").unwrap(),
+        Regexp::from(vec![
+            Pattern::LineStart,
+            Pattern::AnyChar,
+            Pattern::Group {
+                alternations: vec![vec![Pattern::Wildcard]],
+            },
+            Pattern::LineEnd,
+        ])
+    );
+
+    assert_eq!(
+        re_parse(r"(a(?i)b|c)").unwrap(),
+        Regexp::from(vec![Pattern::Alternation {
+            alternations: vec![
+                vec![
+                    Pattern::Literal('a'),
+                    Pattern::PositiveCharGroup(vec!['b', 'B']),
+                ],
+                vec![Pattern::PositiveCharGroup(vec!['c', 'C'])],
             ],
-        }
+            id: 1,
+        }])
     );
 
     assert_eq!(
-        re_parse(r"\1").unwrap(),
-        Regexp {
-            patterns: vec![Pattern::BackReference(1)],
-        }
+        re_parse(r"(?:ab)+").unwrap(),
+        Regexp::from(vec![Pattern::OneOrMore(Box::new(Pattern::Group {
+            alternations: vec![vec![Pattern::Literal('a'), Pattern::Literal('b')]],
+        }))])
     );
+}
 
+#[test]
+fn test_parse_verbose() {
     assert_eq!(
-        re_parse(r"('(cat) and \2') is the same as \1").unwrap(),
-        Regexp {
-            patterns: vec![
-                Pattern::Alternation {
-                    alternations: vec![vec![
-                        Pattern::Literal('\''),
-                        Pattern::Alternation {
-                            alternations: vec![vec![
-                                Pattern::Literal('c'),
-                                Pattern::Literal('a'),
-                                Pattern::Literal('t'),
-                            ]],
-                            id: 2,
-                        },
-                        Pattern::Literal(' '),
-                        Pattern::Literal('a'),
-                        Pattern::Literal('n'),
-                        Pattern::Literal('d'),
-                        Pattern::Literal(' '),
-                        Pattern::BackReference(2),
-                        Pattern::Literal('\''),
-                    ]],
-                    id: 1,
+        re_parse_with_flags(
+            "^ a+ [ b] # comment (with | delimiters)
+            (c | d # another one )
+            ) \\  $ # end",
+            Flags {
+                verbose: true,
+                ..Default::default()
+            }
+        )
+        .unwrap(),
+        Regexp::from(vec![
+            Pattern::Start,
+            Pattern::OneOrMore(Box::new(Pattern::Literal('a'))),
+            Pattern::PositiveCharGroup(vec![' ', 'b']),
+            Pattern::Alternation {
+                alternations: vec![vec![Pattern::Literal('c')], vec![Pattern::Literal('d')]],
+                id: 1,
+            },
+            Pattern::Literal(' '),
+            Pattern::End,
+        ])
+    );
+
+    assert_eq!(
+        re_parse("a b(?x) c d").unwrap(),
+        Regexp::from(vec![
+            Pattern::Literal('a'),
+            Pattern::Literal(' '),
+            Pattern::Literal('b'),
+            Pattern::Literal('c'),
+            Pattern::Literal('d'),
+        ])
+    );
+}
+
+#[test]
+fn test_parse_unicode_property() {
+    let greek = UnicodeProperty::from_name("Greek").unwrap();
+    let letter = UnicodeProperty::from_name("L").unwrap();
+    assert_eq!(greek.name(), "Greek");
+    assert_eq!(letter.name(), "Letter");
+    assert_eq!(UnicodeProperty::from_name("script = grek"), Some(greek));
+    assert_eq!(UnicodeProperty::from_name("gc=letter"), Some(letter));
+    assert_eq!(UnicodeProperty::from_name("foo=letter"), None);
+    assert_eq!(UnicodeProperty::from_name("Klingon"), None);
+    assert_ne!(greek.case_insensitive(), greek);
+    assert!(!greek.is_case_insensitive() && greek.case_insensitive().is_case_insensitive());
+
+    assert_eq!(
+        re_parse(r"\p{Greek}\PL").unwrap(),
+        Regexp::from(vec![
+            Pattern::Property {
+                property: greek,
+                negated: false,
+            },
+            Pattern::Property {
+                property: letter,
+                negated: true,
+            },
+        ])
+    );
+
+    assert_eq!(
+        re_parse(r"(?i)[^a\p{Greek}]").unwrap(),
+        Regexp::from(vec![Pattern::CharClass {
+            items: vec![
+                ClassItem::Char('a'),
+                ClassItem::Char('A'),
+                ClassItem::Property {
+                    property: greek.case_insensitive(),
+                    negated: false,
                 },
-                Pattern::Literal(' '),
-                Pattern::Literal('i'),
-                Pattern::Literal('s'),
-                Pattern::Literal(' '),
-                Pattern::Literal('t'),
-                Pattern::Literal('h'),
-                Pattern::Literal('e'),
-                Pattern::Literal(' '),
-                Pattern::Literal('s'),
+            ],
+            negated: true,
+        }])
+    );
+}
+
+#[test]
+fn test_parse_utf8() {
+    assert_eq!(
+        re_parse(r"é[日本]🦀").unwrap(),
+        Regexp::from(vec![
+            Pattern::Literal('é'),
+            Pattern::PositiveCharGroup(vec!['日', '本']),
+            Pattern::Literal('🦀'),
+        ])
+    );
+}
+
+#[test]
+fn test_parse_bytes() {
+    assert_eq!(
+        re_parse(r"\x41\xe9(?-u)\x41\xe9.[a\xff]").unwrap(),
+        Regexp::from(vec![
+            Pattern::Literal('A'),
+            Pattern::Literal('é'),
+            Pattern::Literal('A'),
+            Pattern::Byte(0xe9),
+            Pattern::ByteClass {
+                bytes: vec![b'\n', b'\r'],
+                negated: true,
+            },
+            Pattern::ByteClass {
+                bytes: vec![0xff, b'a'],
+                negated: false,
+            },
+        ])
+    );
+
+    assert_eq!(
+        re_parse(r"(?i-u)\d[^k]").unwrap(),
+        Regexp::from(vec![
+            Pattern::ByteClass {
+                bytes: b"0123456789".to_vec(),
+                negated: false,
+            },
+            Pattern::ByteClass {
+                bytes: vec![b'k', b'K'],
+                negated: true,
+            },
+        ])
+    );
+
+    // No Kelvin sign, which is not ASCII.
+    let re = re_parse(r"(?-u)(?i)k").unwrap();
+    assert_eq!(
+        re,
+        Regexp::from(vec![Pattern::PositiveCharGroup(vec!['k', 'K'])])
+    );
+    assert!(!re.is_match("\u{212A}"));
+    assert_eq!(
+        re_parse(r"(?i)k").unwrap(),
+        Regexp::from(vec![Pattern::PositiveCharGroup(vec!['k', 'K', '\u{212A}'])])
+    );
+}
+
+#[test]
+fn test_parse_anchors() {
+    assert_eq!(
+        re_parse(r"(^a|b$)\A\z\Z").unwrap(),
+        Regexp::from(vec![
+            Pattern::Alternation {
+                alternations: vec![
+                    vec![Pattern::Start, Pattern::Literal('a')],
+                    vec![Pattern::Literal('b'), Pattern::End],
+                ],
+                id: 1,
+            },
+            Pattern::Start,
+            Pattern::End,
+            Pattern::EndBeforeNewline,
+        ])
+    );
+
+    assert_eq!(
+        re_parse(r"(?m)^\A$\z").unwrap(),
+        Regexp::from(vec![
+            Pattern::LineStart,
+            Pattern::Start,
+            Pattern::LineEnd,
+            Pattern::End,
+        ])
+    );
+
+    // Anchors are parsed where they appear, escaped `$` being a literal even at the end.
+    assert_eq!(
+        re_parse(r"^a|(b$)c\$").unwrap(),
+        Regexp::from(vec![Pattern::Group {
+            alternations: vec![
+                vec![Pattern::Start, Pattern::Literal('a')],
+                vec![
+                    Pattern::Alternation {
+                        alternations: vec![vec![Pattern::Literal('b'), Pattern::End]],
+                        id: 1,
+                    },
+                    Pattern::Literal('c'),
+                    Pattern::Literal('$'),
+                ],
+            ],
+        }])
+    );
+}
+
+#[test]
+fn test_parse_ast() {
+    let flags = Flags::default();
+    let node = |kind, span| Ast { kind, span, flags };
+    let concat = |items: Vec<Ast>, span| node(AstKind::Concat(items), span);
+
+    assert_eq!(
+        parse_ast(r"a|(b)+\z", flags).unwrap(),
+        node(
+            AstKind::Alternation(vec![
+                concat(vec![node(AstKind::Literal('a'), 0..1)], 0..1),
+                concat(
+                    vec![
+                        node(
+                            AstKind::Repetition {
+                                ast: Box::new(node(
+                                    AstKind::Group {
+                                        kind: GroupKind::Capture(1),
+                                        ast: Box::new(concat(
+                                            vec![node(AstKind::Literal('b'), 3..4)],
+                                            3..4
+                                        )),
+                                    },
+                                    2..5
+                                )),
+                                min: 1,
+                                max: None,
+                                lazy: false,
+                            },
+                            2..6
+                        ),
+                        node(AstKind::Assertion(Assertion::End), 6..8),
+                    ],
+                    2..8
+                ),
+            ]),
+            0..8
+        )
+    );
+
+    // Nodes record the flags in effect where they appear.
+    let ast = parse_ast("(?i:[ab])c", flags).unwrap();
+    let AstKind::Concat(items) = ast.kind else {
+        panic!("unexpected {ast:?}");
+    };
+    let AstKind::Group { ast: group, .. } = &items[0].kind else {
+        panic!("unexpected {items:?}");
+    };
+    let case_insensitive = Flags {
+        case_insensitive: true,
+        ..flags
+    };
+    assert_eq!(
+        *group,
+        Box::new(Ast {
+            kind: AstKind::Concat(vec![Ast {
+                kind: AstKind::Class(Class::Bracket {
+                    items: vec![BracketItem::Char('a'), BracketItem::Char('b')],
+                    negated: false,
+                }),
+                span: 4..8,
+                flags: case_insensitive,
+            }]),
+            span: 4..8,
+            flags: case_insensitive,
+        })
+    );
+    assert_eq!((items[0].span.clone(), items[1].flags), (0..9, flags));
+}
+
+/// Kind and span of the error parsing pattern.
+fn parse_error(pattern: &str) -> (ParseErrorKind, Range<usize>) {
+    match re_parse(pattern) {
+        Err(GrepError::Parse(e)) => (e.kind, e.span),
+        other => panic!("{pattern}: unexpected {other:?}"),
+    }
+}
+
+#[test]
+fn test_parse_invalid_pattern() {
+    use ParseErrorKind::*;
+
+    for (pattern, kind, span) in [
+        ("", EmptyPattern, 0..0),
+        ("[abc", UnclosedBracket, 0..1),
+        ("([abc|[def)", UnclosedBracket, 1..2),
+        ("+", NothingToRepeat, 0..1),
+        ("?", NothingToRepeat, 0..1),
+        ("(abc", UnclosedGroup, 4..4),
+        ("a()", EmptyGroup, 2..2),
+        ("(a|)", EmptyGroup, 3..3),
+        ("(?<=ab", UnclosedGroup, 6..6),
+        ("(?q)a", UnknownFlag, 2..3),
+        ("(?i", UnclosedGroup, 3..3),
+        (r"[\p{Greek]", InvalidEscape, 1..3),
+        (r"\p", InvalidEscape, 0..2),
+        (r"\x4", InvalidEscape, 0..2),
+        (r"\xzz", InvalidEscape, 0..2),
+        (r"a\q", InvalidEscape, 1..3),
+        (r"a\", TrailingBackslash, 1..2),
+        (r"(?-u)[é]", UnicodeInByteMode, 6..8),
+        (r"(?-u)\p{Greek}", UnicodeInByteMode, 5..14),
+        (r"^+", NothingToRepeat, 1..2),
+        (r"a$?", NothingToRepeat, 2..3),
+        ("a|", EmptyGroup, 2..2),
+        ("a(?i)+", NothingToRepeat, 5..6),
+    ] {
+        assert_eq!(parse_error(pattern), (kind, span), "{pattern}");
+    }
+
+    assert_eq!(
+        parse_error(r"\p{Klingon}"),
+        (UnknownProperty("Klingon".to_string()), 0..11)
+    );
+    assert_eq!(parse_error("(?<=a+)b"), (UnboundedLookBehind, 0..7));
+    assert_eq!(parse_error(r"(a)(?<!\1)b"), (UnboundedLookBehind, 3..10));
+}
+
+#[test]
+fn test_parse_nesting_limit() {
+    let nested = |depth| format!("{}a{}", "(".repeat(depth), ")".repeat(depth));
+    assert!(re_parse(&nested(128)).is_ok());
+    assert_eq!(
+        parse_error(&nested(129)),
+        (ParseErrorKind::NestingTooDeep, 128..129)
+    );
+
+    // Stacked quantifiers nest too, like groups around them.
+    let stacked = |count| format!("a{}", "+".repeat(count));
+    assert!(re_parse(&stacked(128)).is_ok());
+    assert_eq!(
+        parse_error(&stacked(129)),
+        (ParseErrorKind::NestingTooDeep, 129..130)
+    );
+    assert_eq!(
+        parse_error(&format!("({})", stacked(128))),
+        (ParseErrorKind::NestingTooDeep, 0..1)
+    );
+    assert_eq!(
+        parse_error(&format!("({})+", stacked(127))),
+        (ParseErrorKind::NestingTooDeep, 130..131)
+    );
+}
+
+#[test]
+fn test_parse_error_display() {
+    assert_eq!(
+        re_parse("ab(c").unwrap_err().to_string(),
+        "unclosed group at offset 4\n  ab(c\n      ^"
+    );
+    assert_eq!(
+        re_parse("é(?z)").unwrap_err().to_string(),
+        "unknown inline flag at offset 4\n  é(?z)\n     ^"
+    );
+    assert_eq!(
+        re_parse("a{3,2}").unwrap_err().to_string(),
+        "invalid repetition count at offset 1\n  a{3,2}\n   ^^^^^"
+    );
+    // Only the line where the error starts is shown.
+    assert_eq!(
+        re_parse("(?x) a  # first\n  b{3,2}")
+            .unwrap_err()
+            .to_string(),
+        "invalid repetition count at offset 19\n    b{3,2}\n     ^^^^^"
+    );
+}
+
+#[test]
+fn test_parse_nested_bracket() {
+    assert_eq!(
+        re_parse("[[abc]").unwrap(),
+        Regexp::from(vec![Pattern::PositiveCharGroup(vec!['[', 'a', 'b', 'c'])])
+    );
+    // Brackets are parsed before groups, so `)` in them is a plain char.
+    assert_eq!(
+        re_parse("(a[)]b)").unwrap(),
+        Regexp::from(vec![Pattern::Alternation {
+            alternations: vec![vec![
                 Pattern::Literal('a'),
-                Pattern::Literal('m'),
-                Pattern::Literal('e'),
-                Pattern::Literal(' '),
+                Pattern::PositiveCharGroup(vec![')']),
+                Pattern::Literal('b'),
+            ]],
+            id: 1,
+        }])
+    );
+    assert_eq!(
+        parse_error("a[[:alpha:]]"),
+        (
+            ParseErrorKind::UnsupportedSyntax("[:alpha:]".to_string()),
+            2..11
+        )
+    );
+}
+
+#[test]
+fn test_parse_repetition() {
+    let a = || Box::new(Pattern::Literal('a'));
+    assert_eq!(
+        re_parse("a*").unwrap(),
+        Regexp::from(vec![Pattern::ZeroOrOne(Box::new(Pattern::OneOrMore(a())))])
+    );
+    assert_eq!(
+        re_parse("a{2}").unwrap(),
+        Regexp::from(vec![Pattern::Group {
+            alternations: vec![vec![Pattern::Literal('a'), Pattern::Literal('a')]],
+        }])
+    );
+    assert_eq!(
+        re_parse("a{2,}").unwrap(),
+        Regexp::from(vec![Pattern::Group {
+            alternations: vec![vec![Pattern::Literal('a'), Pattern::OneOrMore(a())]],
+        }])
+    );
+    assert_eq!(
+        re_parse("a{1,3}").unwrap(),
+        Regexp::from(vec![Pattern::Group {
+            alternations: vec![vec![
                 Pattern::Literal('a'),
-                Pattern::Literal('s'),
-                Pattern::Literal(' '),
-                Pattern::BackReference(1),
-            ]
-        }
+                Pattern::ZeroOrOne(Box::new(Pattern::Group {
+                    alternations: vec![vec![Pattern::Literal('a'), Pattern::ZeroOrOne(a())]],
+                })),
+            ]],
+        }])
+    );
+    assert_eq!(re_parse("a{,1}").unwrap(), re_parse("a?").unwrap());
+    assert_eq!(re_parse("a{0,}").unwrap(), re_parse("a*").unwrap());
+    assert_eq!(re_parse("a{1}").unwrap(), re_parse("a").unwrap());
+    // Stacked quantifiers repeat the previous repetition.
+    assert_eq!(
+        re_parse("a{2}+").unwrap(),
+        Regexp::from(vec![Pattern::OneOrMore(Box::new(Pattern::Group {
+            alternations: vec![vec![Pattern::Literal('a'), Pattern::Literal('a')]],
+        }))])
     );
 
+    // Braces not making a repetition are literal.
     assert_eq!(
-        re_parse(r"((abc|def)|ghi)(jkl|mno|(\w+))(pqr)").unwrap(),
-        Regexp {
-            patterns: vec![
-                Pattern::Alternation {
-                    alternations: vec![
-                        vec![Pattern::Alternation {
-                            alternations: vec![
-                                vec![
-                                    Pattern::Literal('a'),
-                                    Pattern::Literal('b'),
-                                    Pattern::Literal('c'),
-                                ],
-                                vec![
-                                    Pattern::Literal('d'),
-                                    Pattern::Literal('e'),
-                                    Pattern::Literal('f'),
-                                ],
-                            ],
-                            id: 2
-                        }],
-                        vec![
-                            Pattern::Literal('g'),
-                            Pattern::Literal('h'),
-                            Pattern::Literal('i'),
-                        ],
-                    ],
-                    id: 1
-                },
-                Pattern::Alternation {
-                    alternations: vec![
-                        vec![
-                            Pattern::Literal('j'),
-                            Pattern::Literal('k'),
-                            Pattern::Literal('l'),
-                        ],
-                        vec![
-                            Pattern::Literal('m'),
-                            Pattern::Literal('n'),
-                            Pattern::Literal('o'),
-                        ],
-                        vec![Pattern::Alternation {
-                            alternations: vec![vec![Pattern::OneOrMore(Box::new(Pattern::Chars))]],
-                            id: 4
-                        }]
-                    ],
-                    id: 3
-                },
-                Pattern::Alternation {
-                    alternations: vec![vec![
-                        Pattern::Literal('p'),
-                        Pattern::Literal('q'),
-                        Pattern::Literal('r'),
-                    ]],
-                    id: 5
-                },
-            ]
+        re_parse("a{x}").unwrap(),
+        Regexp::from(vec![
+            Pattern::Literal('a'),
+            Pattern::Literal('{'),
+            Pattern::Literal('x'),
+            Pattern::Literal('}'),
+        ])
+    );
+    assert_eq!(
+        parse_error("a{3,2}"),
+        (ParseErrorKind::InvalidRepetition, 1..6)
+    );
+    assert_eq!(
+        parse_error("a{1001}"),
+        (ParseErrorKind::InvalidRepetition, 1..7)
+    );
+    assert_eq!(parse_error("*a"), (ParseErrorKind::NothingToRepeat, 0..1));
+
+    // Nested repetitions multiply copies, which are checked before being made.
+    assert_eq!(
+        parse_error("x((a{1000}){1000}){1000}"),
+        (ParseErrorKind::RepetitionTooLarge, 2..17)
+    );
+    assert_eq!(
+        parse_error("(a{0,1000}){0,1000}"),
+        (ParseErrorKind::RepetitionTooLarge, 1..10)
+    );
+}
+
+#[test]
+fn test_parse_escaped_chars() {
+    assert_eq!(
+        re_parse(r"\(a\.\)\*\$").unwrap(),
+        Regexp::from("(a.)*$".chars().map(Pattern::Literal).collect::<Vec<_>>())
+    );
+    assert_eq!(
+        re_parse(r"(\)|\|)").unwrap(),
+        Regexp::from(vec![Pattern::Alternation {
+            alternations: vec![vec![Pattern::Literal(')')], vec![Pattern::Literal('|')]],
+            id: 1,
+        }])
+    );
+}
+
+#[test]
+fn test_parse_basic() {
+    for (basic, extended) in [
+        (r"a\(b\)\1", r"a(b)\1"),
+        (r"a\{2,3\}b*", r"a{2,3}b*"),
+        (r"a\+b\?", r"a+b?"),
+        (r"(a)+b?{1}|", r"\(a\)\+b\?\{1\}\|"),
+        (r"^*a\(*b\)", r"^\*a(\*b)"),
+        (r"a^b$c$", r"a\^b\$c$"),
+        (r"\(^a$\|b\)", r"(^a$|b)"),
+        (r"a\|b", r"a|b"),
+        (r"[(*]\.", r"[(*]\."),
+        (r"\<a\>", r"(?<!\w)(?=\w)a(?<=\w)(?!\w)"),
+        (r"\`a\'", r"\Aa\z"),
+    ] {
+        assert_eq!(basic_to_extended(basic).unwrap(), extended);
+    }
+
+    assert_eq!(
+        re_parse_basic(r"a\|b*", Flags::default()).unwrap(),
+        re_parse("a|b*").unwrap()
+    );
+    for (basic, kind, span) in [
+        (r"a\", ParseErrorKind::TrailingBackslash, 1..2),
+        (r"a\)", ParseErrorKind::UnmatchedParenthesis, 1..3),
+        (r"[a", ParseErrorKind::UnclosedBracket, 0..1),
+    ] {
+        let e = basic_to_extended(basic).unwrap_err();
+        assert_eq!((e.kind, e.span), (kind, span), "{basic}");
+    }
+}
+
+#[test]
+fn test_parse_lazy() {
+    let a = || Box::new(Pattern::Literal('a'));
+    assert_eq!(
+        re_parse("a+?").unwrap(),
+        Regexp::from(vec![Pattern::LazyOneOrMore(a())])
+    );
+    assert_eq!(
+        re_parse("a??").unwrap(),
+        Regexp::from(vec![Pattern::LazyZeroOrOne(a())])
+    );
+    assert_eq!(
+        re_parse("a*?").unwrap(),
+        Regexp::from(vec![Pattern::LazyZeroOrOne(Box::new(
+            Pattern::LazyOneOrMore(a())
+        ))])
+    );
+    assert_eq!(
+        re_parse("a{1,2}?").unwrap(),
+        Regexp::from(vec![Pattern::Group {
+            alternations: vec![vec![Pattern::Literal('a'), Pattern::LazyZeroOrOne(a())]],
+        }])
+    );
+    assert_eq!(
+        re_parse(r"a\Kb").unwrap(),
+        Regexp::from(vec![
+            Pattern::Literal('a'),
+            Pattern::ResetStart,
+            Pattern::Literal('b'),
+        ])
+    );
+    assert_eq!(parse_error(r"\K+"), (ParseErrorKind::NothingToRepeat, 2..3));
+}
+
+#[test]
+fn test_parse_perl() {
+    for (perl, extended) in [
+        (r"(?<year>\d+)-(?P<month>\d+)\k<year>", r"(\d+)-(\d+)\1"),
+        (r"(a)(?'b'b)\k{b}\g{1}\g2\g{-1}(?P=b)", r"(a)(b)\2\1\2\2\2"),
+        (r"a+?b*?c??d{2}?e{1,}", r"a+?b*?c??d{2}?e{1,}"),
+        (
+            r"(?:a)(?=b)(?!c)(?<=d)(?<!e)(?i)f(?s:g)",
+            r"(?:a)(?=b)(?!c)(?<=d)(?<!e)(?i)f(?s:g)",
+        ),
+        (r"\Qa.b(\E+", r"a\x2Eb\x28+"),
+        (r"\Q(x", r"\x28x"),
+        (r"a(?# comment)b", "ab"),
+        (
+            r"\d\D\A\z\Z\K\p{Greek}\pL",
+            r"\d[^0123456789]\A\z\Z\K\p{Greek}\pL",
+        ),
+        (r"\t\x41\x{263a}\.", r"\x09A☺\x2E"),
+        (r"[]a\]-]", r"[\x5Da\x5D\x2D]"),
+        (r"[^a-d\d_]", r"[^abcd0123456789\x5F]"),
+        (r"[\w\s]", r"[\p{L}\p{N}_\p{White_Space}]"),
+        (r"a{,2}", r"a\x7B,2}"),
+        ("a|(b|c)", "a|(b|c)"),
+        (
+            r"(a)(b)(c)(d)(e)(f)(g)(h)(?<i>i)\9\k<i>",
+            r"(a)(b)(c)(d)(e)(f)(g)(h)(i)\9\9",
+        ),
+        ("a.(?s:.).", r"a[^\x0A](?s:.)[^\x0A]"),
+        ("(?x) a # see [x", "(?x) a "),
+        ("(?x:a#)\n)#(?s)(?x-s).", "(?x:a\n)#(?s)(?x-s)[^\\x0A]"),
+    ] {
+        assert_eq!(perl_to_extended(perl).unwrap(), extended, "{perl}");
+    }
+    let verbose = Flags {
+        verbose: true,
+        ..Flags::default()
+    };
+    assert_eq!(
+        perl_to_extended_with_offsets("a # [", verbose).unwrap().0,
+        "a "
+    );
+
+    for (perl, construct) in [
+        ("a++", "++"),
+        ("a{2}+", "{2}+"),
+        ("(?>a)", "(?>"),
+        ("(?|a)", "(?|"),
+        ("(?R)", "(?R"),
+        ("(*SKIP)a", "(*SKIP)"),
+        (r"\Ga", r"\G"),
+        (r"\g<1>", r"\g<"),
+        ("[[:alpha:]]", "[:alpha:]"),
+        ("[a-\\d]", "a-\\d"),
+        ("[\u{0}-\u{3ff}]", "\u{0}-\u{3ff}"),
+        (r"(a)(b)(c)(d)(e)(f)(g)(h)(i)(j)\10", r"\10"),
+    ] {
+        let e = perl_to_extended(perl).unwrap_err();
+        let start = perl.find(construct).unwrap();
+        assert_eq!(
+            (e.kind, e.span),
+            (
+                ParseErrorKind::UnsupportedSyntax(construct.to_string()),
+                start..start + construct.len()
+            ),
+            "{perl}"
+        );
+    }
+
+    for (perl, kind, span) in [
+        (
+            r"(?<a>x)\k<b>",
+            ParseErrorKind::UnknownGroupName("b".to_string()),
+            7..12,
+        ),
+        (
+            r"(?<a>x)(?<a>y)",
+            ParseErrorKind::DuplicateGroupName("a".to_string()),
+            10..11,
+        ),
+        (r"a)", ParseErrorKind::UnmatchedParenthesis, 1..2),
+        (r"[a", ParseErrorKind::UnclosedBracket, 0..1),
+        (r"[z-a]", ParseErrorKind::InvalidRange, 1..4),
+        (r"\g{-2}", ParseErrorKind::InvalidGroupReference, 0..6),
+    ] {
+        let e = perl_to_extended(perl).unwrap_err();
+        assert_eq!((e.kind, e.span), (kind, span), "{perl}");
+    }
+}
+
+#[test]
+fn test_parse_translated_error() {
+    // Errors found once translated point into the pattern as written.
+    for (parse, pattern, kind, span) in [
+        (
+            re_parse_basic as fn(&str, Flags) -> Result<Regexp, GrepError>,
+            r"ab\(cd",
+            ParseErrorKind::UnclosedGroup,
+            6..6,
+        ),
+        (
+            re_parse_basic,
+            r"a{1,2}\{3,1\}",
+            ParseErrorKind::InvalidRepetition,
+            6..13,
+        ),
+        (re_parse_basic, r"a\|", ParseErrorKind::EmptyGroup, 3..3),
+        (
+            re_parse_perl,
+            r"é(?<n>a)x{3,1}",
+            ParseErrorKind::InvalidRepetition,
+            10..15,
+        ),
+        (
+            re_parse_perl,
+            r"[a-z]\p{Foo}",
+            ParseErrorKind::UnknownProperty("Foo".to_string()),
+            5..12,
+        ),
+        (
+            re_parse_perl,
+            r"\Qa(\E(",
+            ParseErrorKind::UnclosedGroup,
+            7..7,
+        ),
+    ] {
+        match parse(pattern, Flags::default()) {
+            Err(GrepError::Parse(e)) => {
+                assert_eq!((&e.kind, &e.span), (&kind, &span), "{pattern}");
+                assert_eq!(e.pattern, pattern);
+            }
+            other => panic!("{pattern}: unexpected {other:?}"),
         }
+    }
+
+    assert_eq!(
+        re_parse_perl(r"é(?<n>a)x{3,1}", Flags::default())
+            .unwrap_err()
+            .to_string(),
+        "invalid repetition count at offset 10\n  é(?<n>a)x{3,1}\n           ^^^^^"
+    );
+    assert_eq!(
+        re_parse_basic(r"a{1,2}\{3,1\}", Flags::default())
+            .unwrap_err()
+            .to_string(),
+        "invalid repetition count at offset 6\n  a{1,2}\\{3,1\\}\n        ^^^^^^^"
     );
+
+    // Wrapping the translation maps its prefix and suffix to the pattern bounds.
+    let (translated, mut offsets) = basic_to_extended_with_offsets(r"a\{3,1\}").unwrap();
+    offsets.wrap(3, 1);
+    let e = re_parse_translated(
+        r"a\{3,1\}",
+        &format!("(?:{translated})"),
+        &offsets,
+        Flags::default(),
+    );
+    assert!(matches!(e, Err(GrepError::Parse(e)) if e.span == (1..8)));
+    assert_eq!(offsets.map(0..3), 0..0);
+    assert_eq!(offsets.map(9..10), 8..8);
+}
+
+/// Xorshift generator, so random patterns are the same on every run.
+struct Rng(u64);
+
+impl Rng {
+    fn below(&mut self, n: usize) -> usize {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 % n as u64) as usize
+    }
 }
 
 #[test]
-fn test_parse_invalid_pattern() {
-    let e = re_parse("").unwrap_err();
-    assert_eq!(e, GrepError::InvalidPattern);
+fn test_parse_random_patterns() {
+    // Syntax chars of every dialect, with multi-byte ones to catch bad slicing.
+    const PIECES: &[&str] = &[
+        "a", "b", "é", "☺", "(", ")", "[", "]", "{", "}", ",", "0", "1", "2", "^", "$", "*", "+",
+        "?", "|", "\\", ".", "-", ":", "<", ">", "=", "!", "#", "'", " ", "\n", "_", "d", "x", "p",
+        "P", "k", "g", "i", "u", "K", "Q", "E", "{2}", "{1,", "(?", "(?<", "(?P", "(?x)", "(?-u)",
+        "[^", "[:", "\\p{L}", "\\x4", "\\x{", "\\k<", "\\g{",
+    ];
+
+    let mut rng = Rng(0x9e37_79b9_7f4a_7c15);
+    let random = iter::repeat_with(|| {
+        let len = rng.below(16);
+        (0..len).map(|_| PIECES[rng.below(PIECES.len())]).collect()
+    });
+    // Patterns that used to panic, then random ones.
+    let known = ["[[abc]", "(é", "(?é)", r"\g☺", r"[(-\☺]", r"[a-\d☺]"].map(String::from);
+
+    for pattern in known.into_iter().chain(random.take(50_000)) {
+        for flags in [
+            Flags::default(),
+            Flags {
+                verbose: true,
+                bytes: true,
+                ..Flags::default()
+            },
+        ] {
+            // Any pattern is either parsed or rejected, without panicking.
+            let _ = re_parse_with_flags(&pattern, flags);
+            let _ = re_parse_basic(&pattern, flags);
+            let _ = re_parse_perl(&pattern, flags);
+        }
+    }
+}
+
+#[test]
+fn test_parse_huge_patterns() {
+    // Deep or huge patterns are rejected, instead of overflowing the test thread stack.
+    let nested =
+        |open: &str, close: &str| format!("{}a{}", open.repeat(100_000), close.repeat(100_000));
+    let stacked = format!("a{}", "?".repeat(100_000));
+    for (parse, patterns) in [
+        (
+            re_parse_with_flags as fn(&str, Flags) -> Result<Regexp, GrepError>,
+            [
+                nested("(", ")"),
+                stacked.clone(),
+                "((a{1000}){1000}){1000}".to_string(),
+            ],
+        ),
+        (
+            re_parse_perl,
+            [
+                nested("(?:", ")"),
+                stacked.clone(),
+                "((?:a{1000}){1000}){1000}".to_string(),
+            ],
+        ),
+        (
+            re_parse_basic,
+            [
+                nested(r"\(", r"\)"),
+                format!("a{}", r"\?".repeat(100_000)),
+                r"\(\(a\{1000\}\)\{1000\}\)\{1000\}".to_string(),
+            ],
+        ),
+    ] {
+        for pattern in patterns {
+            assert!(matches!(
+                parse(&pattern, Flags::default()),
+                Err(GrepError::Parse(_))
+            ));
+        }
+    }
+}
+
+/// Random pattern trees, in the canonical form parsing produces.
+struct TreeGenerator {
+    rng: Rng,
+    capture_count: usize,
+}
 
-    let e = re_parse("[abc").unwrap_err();
-    assert_eq!(e, GrepError::InvalidPattern);
+impl TreeGenerator {
+    fn sequence(&mut self, depth: usize) -> Vec<Pattern> {
+        let len = 1 + self.rng.below(4);
+        (0..len).map(|_| self.pattern(depth)).collect()
+    }
 
-    let e = re_parse("([abc|[def)").unwrap_err();
-    assert_eq!(e, GrepError::InvalidPattern);
+    /// Sequence with a consuming pattern somewhere, so its group can be repeated.
+    fn consuming_sequence(&mut self, depth: usize) -> Vec<Pattern> {
+        let len = self.rng.below(4);
+        let consuming = self.rng.below(len + 1);
+        (0..=len)
+            .map(|idx| {
+                if idx == consuming {
+                    self.consuming(depth)
+                } else {
+                    self.pattern(depth)
+                }
+            })
+            .collect()
+    }
 
-    let e = re_parse("+").unwrap_err();
-    assert_eq!(e, GrepError::InvalidPattern);
+    fn alternations(&mut self, depth: usize) -> Vec<Vec<Pattern>> {
+        let len = 1 + self.rng.below(3);
+        (0..len).map(|_| self.sequence(depth)).collect()
+    }
 
-    let e = re_parse("?").unwrap_err();
-    assert_eq!(e, GrepError::InvalidPattern);
+    fn consuming_alternations(&mut self, depth: usize) -> Vec<Vec<Pattern>> {
+        let len = 1 + self.rng.below(3);
+        let consuming = self.rng.below(len);
+        (0..len)
+            .map(|idx| {
+                if idx == consuming {
+                    self.consuming_sequence(depth)
+                } else {
+                    self.sequence(depth)
+                }
+            })
+            .collect()
+    }
+
+    fn pattern(&mut self, depth: usize) -> Pattern {
+        match self.rng.below(13) {
+            0 => Pattern::Start,
+            1 => Pattern::End,
+            2 => Pattern::LineStart,
+            3 => Pattern::LineEnd,
+            4 => Pattern::EndBeforeNewline,
+            5 => Pattern::ResetStart,
+            6 if depth > 0 => Pattern::LookAhead {
+                alternations: self.alternations(depth - 1),
+                negative: self.rng.below(2) == 0,
+            },
+            // Lookbehind length must be bounded.
+            7 => Pattern::LookBehind {
+                alternations: (0..1 + self.rng.below(3))
+                    .map(|_| (0..1 + self.rng.below(3)).map(|_| self.unit()).collect())
+                    .collect(),
+                negative: self.rng.below(2) == 0,
+            },
+            // Anything repeated zero times, the only empty group, dropping its groups.
+            8 => {
+                self.capture_count += self.rng.below(3);
+                Pattern::Group {
+                    alternations: vec![Vec::new()],
+                }
+            }
+            // Groups not repeated may only match the empty string.
+            9 if depth > 0 => {
+                self.capture_count += 1;
+                let id = self.capture_count;
+                Pattern::Alternation {
+                    alternations: self.alternations(depth - 1),
+                    id,
+                }
+            }
+            10 if depth > 0 => Pattern::Group {
+                alternations: self.alternations(depth - 1),
+            },
+            _ => self.consuming(depth),
+        }
+    }
 
-    let e = re_parse("(abc").unwrap_err();
-    assert_eq!(e, GrepError::InvalidPattern);
+    /// Pattern consuming chars, which can be repeated.
+    fn consuming(&mut self, depth: usize) -> Pattern {
+        if depth == 0 {
+            return self.unit();
+        }
+        match self.rng.below(10) {
+            0 => Pattern::OneOrMore(Box::new(self.consuming(depth - 1))),
+            1 => Pattern::ZeroOrOne(Box::new(self.consuming(depth - 1))),
+            2 => Pattern::LazyOneOrMore(Box::new(self.consuming(depth - 1))),
+            3 => Pattern::LazyZeroOrOne(Box::new(self.consuming(depth - 1))),
+            4 => {
+                // Groups are numbered in opening order.
+                self.capture_count += 1;
+                let id = self.capture_count;
+                Pattern::Alternation {
+                    alternations: self.consuming_alternations(depth - 1),
+                    id,
+                }
+            }
+            5 => Pattern::Group {
+                alternations: self.consuming_alternations(depth - 1),
+            },
+            6 => Pattern::BackReference(1 + self.rng.below(9)),
+            7 => {
+                // Copies of a repeated group share its id.
+                self.capture_count += 1;
+                let group = Pattern::Alternation {
+                    id: self.capture_count,
+                    alternations: self.consuming_alternations(depth - 1),
+                };
+                let min = self.rng.below(4);
+                let max = match self.rng.below(3) {
+                    0 => None,
+                    1 => Some(min.max(1)),
+                    _ => Some(min + 1 + self.rng.below(2)),
+                };
+                repeat(group, min, max, self.rng.below(2) == 0)
+            }
+            _ => self.unit(),
+        }
+    }
+
+    /// Pattern matching a single char or byte.
+    fn unit(&mut self) -> Pattern {
+        const CHARS: &[char] = &[
+            'a', 'B', '0', 'é', '☺', '\n', ' ', '#', '-', ',', '\\', '.', '+', '*', '?', '(', ')',
+            '|', '[', ']', '{', '}', '^', '$',
+        ];
+        const PROPERTIES: &[&str] = &["L", "Greek", "Nd", "White_Space"];
+        const BYTES: &[u8] = &[0x80, 0xe9, 0xff, 0x00, b'a', b'Z', b']', b'\\', b'^'];
+
+        let chars = |rng: &mut Rng| {
+            let mut chars = Vec::new();
+            for _ in 0..rng.below(4) {
+                let c = CHARS[rng.below(CHARS.len())];
+                if !chars.contains(&c) {
+                    chars.push(c);
+                }
+            }
+            chars
+        };
+        let rng = &mut self.rng;
+        let property = |rng: &mut Rng| {
+            UnicodeProperty::from_name(PROPERTIES[rng.below(PROPERTIES.len())]).unwrap()
+        };
+
+        match rng.below(11) {
+            0 => Pattern::Digit,
+            1 => Pattern::Chars,
+            2 => Pattern::Wildcard,
+            3 => Pattern::AnyChar,
+            4 => Pattern::PositiveCharGroup(chars(rng)),
+            5 => Pattern::NegativeCharGroup(chars(rng)),
+            6 => Pattern::Property {
+                property: property(rng),
+                negated: rng.below(2) == 0,
+            },
+            7 => {
+                // Class items keep their order, with at least one property.
+                let mut items: Vec<_> = chars(rng).into_iter().map(ClassItem::Char).collect();
+                let idx = rng.below(items.len() + 1);
+                let negated = rng.below(2) == 0;
+                items.insert(
+                    idx,
+                    ClassItem::Property {
+                        property: property(rng),
+                        negated,
+                    },
+                );
+                Pattern::CharClass {
+                    items,
+                    negated: rng.below(2) == 0,
+                }
+            }
+            8 => Pattern::Byte(0x80 + rng.below(0x80) as u8),
+            9 => {
+                // Non-ASCII bytes come before ASCII ones, like in parsed classes.
+                let mut bytes = Vec::new();
+                for _ in 0..rng.below(5) {
+                    let byte = BYTES[rng.below(BYTES.len())];
+                    if !bytes.contains(&byte) {
+                        bytes.push(byte);
+                    }
+                }
+                bytes.sort_by_key(u8::is_ascii);
+                Pattern::ByteClass {
+                    bytes,
+                    negated: rng.below(2) == 0,
+                }
+            }
+            _ => Pattern::Literal(CHARS[rng.below(CHARS.len())]),
+        }
+    }
+}
+
+/// Counted repetition expanded to copies of pattern, like parsing does.
+fn repeat(pattern: Pattern, min: usize, max: Option<usize>, lazy: bool) -> Pattern {
+    type Quantifier = fn(Box<Pattern>) -> Pattern;
+    let (one_or_more, zero_or_one): (Quantifier, Quantifier) = if lazy {
+        (Pattern::LazyOneOrMore, Pattern::LazyZeroOrOne)
+    } else {
+        (Pattern::OneOrMore, Pattern::ZeroOrOne)
+    };
+
+    let mut sequence = vec![pattern.clone(); min];
+    match max {
+        None if min == 0 => return zero_or_one(Box::new(one_or_more(Box::new(pattern)))),
+        None => {
+            sequence.pop();
+            sequence.push(one_or_more(Box::new(pattern)));
+        }
+        Some(max) => {
+            let mut optional = None;
+            for _ in min..max {
+                let inner = match optional {
+                    None => pattern.clone(),
+                    Some(optional) => Pattern::Group {
+                        alternations: vec![vec![pattern.clone(), optional]],
+                    },
+                };
+                optional = Some(zero_or_one(Box::new(inner)));
+            }
+            sequence.extend(optional);
+        }
+    }
+
+    match <[Pattern; 1]>::try_from(sequence) {
+        Ok([pattern]) => pattern,
+        Err(sequence) => Pattern::Group {
+            alternations: vec![sequence],
+        },
+    }
 }
 
 #[test]
-#[should_panic]
-fn test_valid_unsupported_pattern2() {
-    let e = re_parse("[[abc]").unwrap_err();
-    assert_eq!(e, GrepError::InvalidPattern);
+fn test_display_random_trees() {
+    let mut generator = TreeGenerator {
+        rng: Rng(0x2545_f491_4f6c_dd1d),
+        capture_count: 0,
+    };
+
+    for _ in 0..20_000 {
+        generator.capture_count = 0;
+        let depth = generator.rng.below(4);
+        let re = Regexp::from(generator.sequence(depth));
+
+        // Printed pattern is parsed back to the same tree.
+        let printed = re.to_string();
+        assert_eq!(re_parse(&printed).ok(), Some(re), "{printed}");
+    }
 }